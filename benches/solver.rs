@@ -0,0 +1,229 @@
+#![allow(clippy::needless_return)]
+
+use fluid_simulation::flow_field::generate_flow_field;
+use fluid_simulation::mass_distr::load_mass_distribution;
+use fluid_simulation::save_frame::{save_frame, PngOptions};
+use fluid_simulation::simulate::simulate;
+use fluid_simulation::utility::{grid_dimensions, FlowField, SUPPORTED_RESOLUTIONS};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::{path::PathBuf, time::Duration};
+
+fn thread_counts() -> Vec<usize> {
+    let available = std::thread::available_parallelism()
+        .map(|threads| threads.get())
+        .unwrap_or(1);
+    let mut counts = vec![1];
+
+    while counts[counts.len() - 1] * 2 <= available {
+        counts.push(counts[counts.len() - 1] * 2);
+    }
+    if counts[counts.len() - 1] != available {
+        counts.push(available);
+    }
+
+    return counts;
+}
+
+fn thread_pool(threads: usize) -> ThreadPool {
+    return ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .expect("cannot build the benchmark thread pool");
+}
+
+fn bench_directory() -> PathBuf {
+    let directory = std::env::temp_dir().join("fluid_simulation_bench");
+    std::fs::create_dir_all(&directory).expect("cannot create the benchmark directory");
+
+    return directory;
+}
+
+fn mass_distr_image(res_x: usize, res_y: usize) -> String {
+    let path = bench_directory().join(format!("mass_{}x{}.png", res_x / 2, res_y / 2));
+    let image = image::ImageBuffer::from_fn(res_x as u32 / 2, res_y as u32 / 2, |x, y| {
+        image::Luma([((x ^ y) & 0xff) as u8])
+    });
+    image
+        .save(&path)
+        .expect("cannot write the benchmark mass distribution");
+
+    return path.to_string_lossy().into_owned();
+}
+
+fn initial_state(res_x: usize, res_y: usize) -> (FlowField, Vec<f32>) {
+    let mut flow_field = FlowField::new(res_x * res_y);
+    generate_flow_field(
+        &mut flow_field,
+        &mut Vec::new(),
+        res_x,
+        res_y,
+        300.0,
+        0.0,
+        0.0,
+        0.0,
+    );
+
+    let mass_distr = (0..res_x * res_y)
+        .map(|index| ((index % res_x) / 16 + (index / res_x) / 16) as f32 % 2.0)
+        .collect();
+
+    return (flow_field, mass_distr);
+}
+
+fn bench_simulate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("simulate");
+    group
+        .sample_size(10)
+        .measurement_time(Duration::from_secs(5));
+
+    for resolution in SUPPORTED_RESOLUTIONS {
+        let (res_x, res_y) = grid_dimensions(resolution).unwrap();
+        let (flow_field, mut mass_distr) = initial_state(res_x, res_y);
+        let mut mass_buffer = vec![0.0; res_x * res_y];
+        group.throughput(Throughput::Elements((res_x * res_y) as u64));
+
+        for threads in thread_counts() {
+            let pool = thread_pool(threads);
+
+            group.bench_with_input(
+                BenchmarkId::new(format!("{}p", resolution), format!("{} threads", threads)),
+                &threads,
+                |b, _| {
+                    b.iter(|| {
+                        pool.install(|| {
+                            simulate(&flow_field, &mut mass_distr, &mut mass_buffer, res_x, res_y)
+                        })
+                    })
+                },
+            );
+        }
+    }
+
+    group.finish();
+}
+
+fn bench_generate_flow_field(c: &mut Criterion) {
+    let mut group = c.benchmark_group("generate_flow_field");
+    group
+        .sample_size(10)
+        .measurement_time(Duration::from_secs(10));
+
+    for resolution in SUPPORTED_RESOLUTIONS {
+        let (res_x, res_y) = grid_dimensions(resolution).unwrap();
+        let mut flow_field = FlowField::new(res_x * res_y);
+        let mut noise_buffer = vec![0.0; res_x * res_y];
+        group.throughput(Throughput::Elements((res_x * res_y) as u64));
+
+        for threads in thread_counts() {
+            let pool = thread_pool(threads);
+
+            group.bench_with_input(
+                BenchmarkId::new(format!("{}p", resolution), format!("{} threads", threads)),
+                &threads,
+                |b, _| {
+                    let mut offset_z = 0.0;
+
+                    b.iter(|| {
+                        offset_z += 0.3;
+                        pool.install(|| {
+                            generate_flow_field(
+                                &mut flow_field,
+                                &mut noise_buffer,
+                                res_x,
+                                res_y,
+                                300.0,
+                                0.0,
+                                0.0,
+                                offset_z,
+                            )
+                        })
+                    })
+                },
+            );
+        }
+    }
+
+    group.finish();
+}
+
+fn bench_load_mass_distribution(c: &mut Criterion) {
+    let mut group = c.benchmark_group("load_mass_distribution");
+    group.sample_size(10);
+
+    for resolution in SUPPORTED_RESOLUTIONS {
+        let (res_x, res_y) = grid_dimensions(resolution).unwrap();
+        let mass_distr_file_path = mass_distr_image(res_x, res_y);
+        group.throughput(Throughput::Elements((res_x * res_y) as u64));
+
+        for threads in thread_counts() {
+            let pool = thread_pool(threads);
+
+            group.bench_with_input(
+                BenchmarkId::new(format!("{}p", resolution), format!("{} threads", threads)),
+                &threads,
+                |b, _| {
+                    b.iter(|| {
+                        pool.install(|| {
+                            load_mass_distribution(&mass_distr_file_path, res_x, res_y).unwrap()
+                        })
+                    })
+                },
+            );
+        }
+    }
+
+    group.finish();
+}
+
+fn bench_save_frame(c: &mut Criterion) {
+    let mut group = c.benchmark_group("save_frame");
+    group.sample_size(10);
+
+    for resolution in SUPPORTED_RESOLUTIONS {
+        let (res_x, res_y) = grid_dimensions(resolution).unwrap();
+        let (_, mass_distr) = initial_state(res_x, res_y);
+        let image_file_path = bench_directory()
+            .join(format!("frame_{}p.png", resolution))
+            .to_string_lossy()
+            .into_owned();
+        group.throughput(Throughput::Elements((res_x * res_y) as u64));
+
+        for threads in thread_counts() {
+            let pool = thread_pool(threads);
+
+            group.bench_with_input(
+                BenchmarkId::new(format!("{}p", resolution), format!("{} threads", threads)),
+                &threads,
+                |b, _| {
+                    b.iter(|| {
+                        pool.install(|| {
+                            save_frame(
+                                &image_file_path,
+                                &mass_distr,
+                                res_x,
+                                res_y,
+                                170.0,
+                                0.03,
+                                &PngOptions::default(),
+                            )
+                            .unwrap()
+                        })
+                    })
+                },
+            );
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_simulate,
+    bench_generate_flow_field,
+    bench_load_mass_distribution,
+    bench_save_frame
+);
+criterion_main!(benches);
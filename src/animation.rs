@@ -0,0 +1,244 @@
+use crate::utility::FlowField;
+
+use rayon::prelude::*;
+use serde::Deserialize;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnimatedParameter {
+    FlowFieldScale,
+    FlowSpeed,
+    EvolutionSpeed,
+    ToneMidpoint,
+    ToneSlope,
+    DecayRate,
+    NoiseForcing,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Interpolation {
+    Linear,
+    Ease,
+    Step,
+}
+
+/// Value of a parameter at a frame; `interpolation` leads from this keyframe to the next one.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Keyframe {
+    pub frame: usize,
+    pub value: f64,
+    #[serde(default)]
+    pub interpolation: Interpolation,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ParameterTrack {
+    pub parameter: AnimatedParameter,
+    pub keyframes: Vec<Keyframe>,
+}
+
+impl Default for Interpolation {
+    fn default() -> Self {
+        return Interpolation::Linear;
+    }
+}
+
+impl fmt::Display for AnimatedParameter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AnimatedParameter::FlowFieldScale => write!(f, "flow_field_scale"),
+            AnimatedParameter::FlowSpeed => write!(f, "flow_speed"),
+            AnimatedParameter::EvolutionSpeed => write!(f, "evolution_speed"),
+            AnimatedParameter::ToneMidpoint => write!(f, "tone_midpoint"),
+            AnimatedParameter::ToneSlope => write!(f, "tone_slope"),
+            AnimatedParameter::DecayRate => write!(f, "decay_rate"),
+            AnimatedParameter::NoiseForcing => write!(f, "noise_forcing"),
+        }
+    }
+}
+
+impl fmt::Display for Interpolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Interpolation::Linear => write!(f, "linear"),
+            Interpolation::Ease => write!(f, "ease"),
+            Interpolation::Step => write!(f, "step"),
+        }
+    }
+}
+
+impl AnimatedParameter {
+    /// Whether `value` is allowed for the parameter, and a description of the allowed values.
+    pub fn accepts(&self, value: f64) -> (bool, &'static str) {
+        match self {
+            AnimatedParameter::FlowFieldScale => (
+                value >= 1.0 && value.is_finite(),
+                "a finite number of at least 1.0",
+            ),
+            AnimatedParameter::ToneSlope => (
+                value > 0.0 && value.is_finite(),
+                "a finite number greater than 0.0",
+            ),
+            AnimatedParameter::EvolutionSpeed | AnimatedParameter::ToneMidpoint => {
+                (value.is_finite(), "a finite number")
+            }
+            // Every unit of speed costs an advection sub-step.
+            AnimatedParameter::FlowSpeed => {
+                ((0.0..=16.0).contains(&value), "a number from 0.0 to 16.0")
+            }
+            AnimatedParameter::DecayRate | AnimatedParameter::NoiseForcing => (
+                value >= 0.0 && value.is_finite(),
+                "a finite number of at least 0.0",
+            ),
+        }
+    }
+}
+
+impl ParameterTrack {
+    /// Value at a (fractional) frame; the track holds its first and last values outside of
+    /// the keyframes.
+    pub fn value(&self, frame: f64) -> f64 {
+        let next = self
+            .keyframes
+            .iter()
+            .position(|keyframe| keyframe.frame as f64 > frame);
+
+        match next {
+            Some(0) => {
+                return self.keyframes[0].value;
+            }
+            Some(next) => {
+                let (from, to) = (&self.keyframes[next - 1], &self.keyframes[next]);
+                let t = (frame - from.frame as f64) / (to.frame - from.frame) as f64;
+                let weight = match from.interpolation {
+                    Interpolation::Linear => t,
+                    Interpolation::Ease => t * t * (3.0 - 2.0 * t),
+                    Interpolation::Step => 0.0,
+                };
+
+                return from.value + (to.value - from.value) * weight;
+            }
+            None => {
+                return self.keyframes.last().map_or(0.0, |keyframe| keyframe.value);
+            }
+        }
+    }
+}
+
+/// The parameter tracks of a run; parameters without a track keep their configured value.
+pub struct Animation {
+    tracks: Vec<ParameterTrack>,
+}
+
+impl Animation {
+    pub fn new(tracks: &[ParameterTrack]) -> Animation {
+        return Animation {
+            tracks: tracks.to_vec(),
+        };
+    }
+
+    pub fn animates(&self, parameter: AnimatedParameter) -> bool {
+        return self.track(parameter).is_some();
+    }
+
+    pub fn value(&self, parameter: AnimatedParameter, frame: f64, default: f64) -> f64 {
+        match self.track(parameter) {
+            Some(track) => {
+                return track.value(frame);
+            }
+            None => {
+                return default;
+            }
+        }
+    }
+
+    fn track(&self, parameter: AnimatedParameter) -> Option<&ParameterTrack> {
+        return self
+            .tracks
+            .iter()
+            .find(|track| track.parameter == parameter);
+    }
+}
+
+/// Writes `flow_field` multiplied by `factor` into `scaled`.
+pub fn scale_flow_field(scaled: &mut FlowField, flow_field: &FlowField, factor: f32) {
+    scaled
+        .x
+        .par_iter_mut()
+        .zip(flow_field.x.par_iter())
+        .for_each(|(scaled, value)| *scaled = value * factor);
+    scaled
+        .y
+        .par_iter_mut()
+        .zip(flow_field.y.par_iter())
+        .for_each(|(scaled, value)| *scaled = value * factor);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(interpolation: Interpolation) -> ParameterTrack {
+        return ParameterTrack {
+            parameter: AnimatedParameter::FlowSpeed,
+            keyframes: vec![
+                Keyframe {
+                    frame: 10,
+                    value: 1.0,
+                    interpolation,
+                },
+                Keyframe {
+                    frame: 20,
+                    value: 3.0,
+                    interpolation: Interpolation::Linear,
+                },
+            ],
+        };
+    }
+
+    #[test]
+    fn values_are_held_outside_of_the_keyframes() {
+        let track = track(Interpolation::Linear);
+
+        assert_eq!(track.value(0.0), 1.0);
+        assert_eq!(track.value(10.0), 1.0);
+        assert_eq!(track.value(20.0), 3.0);
+        assert_eq!(track.value(100.0), 3.0);
+    }
+
+    #[test]
+    fn interpolation_modes_shape_the_segment() {
+        let linear = track(Interpolation::Linear);
+        let ease = track(Interpolation::Ease);
+        let step = track(Interpolation::Step);
+
+        assert!((linear.value(12.5) - 1.5).abs() < 1e-9);
+        assert!((linear.value(15.0) - 2.0).abs() < 1e-9);
+
+        // Smoothstep: slow at both ends, through the midpoint in the middle.
+        assert!(ease.value(12.5) < linear.value(12.5));
+        assert!((ease.value(15.0) - 2.0).abs() < 1e-9);
+        assert!(ease.value(17.5) > linear.value(17.5));
+
+        assert_eq!(step.value(19.9), 1.0);
+        assert_eq!(step.value(20.0), 3.0);
+    }
+
+    #[test]
+    fn parameters_without_a_track_keep_their_value() {
+        let animation = Animation::new(&[track(Interpolation::Linear)]);
+
+        assert!(animation.animates(AnimatedParameter::FlowSpeed));
+        assert!(animation.animates(AnimatedParameter::ToneSlope) == false);
+        assert_eq!(
+            animation.value(AnimatedParameter::FlowSpeed, 15.0, 7.0),
+            2.0
+        );
+        assert_eq!(
+            animation.value(AnimatedParameter::ToneSlope, 15.0, 0.03),
+            0.03
+        );
+    }
+}
@@ -0,0 +1,166 @@
+use crate::simulate::simulate;
+use crate::utility::FlowField;
+
+use rayon::prelude::*;
+
+/// Boussinesq buoyancy: hot fluid rises against gravity and dense fluid sinks with it.
+/// The temperature (relative to the ambient one) is advected like the mass and cools
+/// down towards the ambient temperature.
+pub struct Buoyancy {
+    res_x: usize,
+    res_y: usize,
+    gravity_x: f32,
+    gravity_y: f32,
+    buoyancy: f32,
+    density_weight: f32,
+    cooling_rate: f32,
+    temperature: Vec<f32>,
+    temperature_buffer: Vec<f32>,
+}
+
+impl Buoyancy {
+    pub fn new(
+        res_x: usize,
+        res_y: usize,
+        gravity_direction: [f32; 2],
+        buoyancy: f32,
+        density_weight: f32,
+        cooling_rate: f32,
+        temperature: Vec<f32>,
+    ) -> Buoyancy {
+        let length = (gravity_direction[0].powi(2) + gravity_direction[1].powi(2)).sqrt();
+
+        return Buoyancy {
+            res_x,
+            res_y,
+            gravity_x: gravity_direction[0] / length,
+            gravity_y: gravity_direction[1] / length,
+            buoyancy,
+            density_weight,
+            cooling_rate,
+            temperature_buffer: vec![0.0; res_x * res_y],
+            temperature,
+        };
+    }
+
+    pub fn temperature(&self) -> &Vec<f32> {
+        return &self.temperature;
+    }
+
+    /// Adds `(density_weight * mass - buoyancy * temperature) * gravity` to `flow_field`.
+    pub fn apply(&self, flow_field: &mut FlowField, mass_distr: &Vec<f32>) {
+        let (gravity_x, gravity_y) = (self.gravity_x, self.gravity_y);
+        let (buoyancy, density_weight) = (self.buoyancy, self.density_weight);
+
+        flow_field
+            .x
+            .par_iter_mut()
+            .zip(flow_field.y.par_iter_mut())
+            .zip(mass_distr.par_iter().zip(self.temperature.par_iter()))
+            .for_each(|((u, v), (mass, temperature))| {
+                let weight = density_weight * mass - buoyancy * temperature;
+
+                *u += weight * gravity_x;
+                *v += weight * gravity_y;
+            });
+    }
+
+    /// Carries the temperature along `flow_field` and cools it down for `fraction` of a
+    /// simulation step.
+    pub fn advect(&mut self, flow_field: &FlowField, fraction: f32) {
+        simulate(
+            flow_field,
+            &mut self.temperature,
+            &mut self.temperature_buffer,
+            self.res_x,
+            self.res_y,
+        );
+
+        if self.cooling_rate > 0.0 {
+            let retained = (1.0 - self.cooling_rate).powf(fraction);
+
+            self.temperature
+                .par_iter_mut()
+                .for_each(|temperature| *temperature *= retained);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::navier_stokes::NavierStokesSolver;
+
+    const RES_X: usize = 64;
+    const RES_Y: usize = 48;
+
+    fn hot_blob() -> Vec<f32> {
+        return (0..RES_X * RES_Y)
+            .map(|index| {
+                let (x, y) = ((index % RES_X) as f32 - 32.0, (index / RES_X) as f32 - 32.0);
+
+                (-(x * x + y * y) / 20.0).exp()
+            })
+            .collect();
+    }
+
+    /// Temperature-weighted mean row of the blob.
+    fn height(temperature: &[f32]) -> f32 {
+        let total: f32 = temperature.iter().sum();
+
+        return temperature
+            .iter()
+            .enumerate()
+            .map(|(index, value)| (index / RES_X) as f32 * value)
+            .sum::<f32>()
+            / total;
+    }
+
+    #[test]
+    fn force_points_against_gravity_for_hot_fluid() {
+        let buoyancy = Buoyancy::new(RES_X, RES_Y, [0.0, 2.0], 0.1, 0.05, 0.0, hot_blob());
+        let mut flow_field = FlowField::new(RES_X * RES_Y);
+        let index = 32 * RES_X + 32;
+
+        buoyancy.apply(&mut flow_field, &vec![0.0; RES_X * RES_Y]);
+        assert_eq!(flow_field.x[index], 0.0);
+        assert!((flow_field.y[index] + 0.1).abs() < 1e-6);
+
+        // Dense fluid as heavy as it is hot is in balance.
+        let mut flow_field = FlowField::new(RES_X * RES_Y);
+        buoyancy.apply(
+            &mut flow_field,
+            &hot_blob().iter().map(|value| 2.0 * value).collect(),
+        );
+        assert!(flow_field.y.iter().all(|value| value.abs() < 1e-6));
+    }
+
+    #[test]
+    fn hot_fluid_rises() {
+        let mut solver = NavierStokesSolver::new(RES_X, RES_Y, 0.0, 40, 0.0, None);
+        let mut buoyancy = Buoyancy::new(RES_X, RES_Y, [0.0, 1.0], 0.05, 0.0, 0.0, hot_blob());
+        let mass_distr = vec![0.0; RES_X * RES_Y];
+        let initial_height = height(buoyancy.temperature());
+
+        solver.seed(&FlowField::new(RES_X * RES_Y));
+        for _ in 0..20 {
+            buoyancy.apply(solver.velocity_mut(), &mass_distr);
+            solver.step(None);
+            buoyancy.advect(solver.velocity(), 1.0);
+        }
+
+        assert!(height(buoyancy.temperature()) < initial_height - 1.0);
+    }
+
+    #[test]
+    fn temperature_cools_down() {
+        let mut buoyancy = Buoyancy::new(RES_X, RES_Y, [0.0, 1.0], 0.05, 0.0, 0.5, hot_blob());
+        let initial: f32 = buoyancy.temperature().iter().sum();
+
+        buoyancy.advect(&FlowField::new(RES_X * RES_Y), 1.0);
+
+        let cooled: f32 = buoyancy.temperature().iter().sum();
+        assert!((cooled - 0.5 * initial).abs() < 1e-3 * initial);
+    }
+}
@@ -0,0 +1,489 @@
+use crate::contact_sheet::ContactSheetOptions;
+use crate::progress::ProgressMode;
+use crate::sim_ctrl::load_configuration;
+use crate::utility::Configuration;
+
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+use std::{error::Error, ffi::OsString};
+
+pub const PREVIEW_FRAMES: usize = 4;
+
+const SUBCOMMANDS: [&str; 8] = [
+    "run",
+    "validate",
+    "preview",
+    "sweep",
+    "contact-sheet",
+    "info",
+    "init",
+    "help",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Subcommand {
+    Run,
+    Validate,
+    Preview,
+    Sweep,
+    ContactSheet,
+    Info,
+    Init,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Overrides {
+    pub frames_number: Option<usize>,
+    pub target_resolution: Option<usize>,
+    pub seed: Option<u64>,
+    pub output_directory_path: Option<String>,
+    pub threads: Option<usize>,
+    pub progress: Option<ProgressMode>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invocation {
+    pub subcommand: Subcommand,
+    pub config_file_path: Option<String>,
+    pub overrides: Overrides,
+    pub force: bool,
+    pub directory_path: Option<String>,
+    pub contact_sheet: ContactSheetOptions,
+}
+
+impl Overrides {
+    pub fn apply(&self, config: &mut Configuration) {
+        if let Some(frames_number) = self.frames_number {
+            config.frames_number = frames_number;
+        }
+        if let Some(target_resolution) = self.target_resolution {
+            config.target_resolution = target_resolution;
+        }
+        if let Some(seed) = self.seed {
+            config.seed = Some(seed);
+            config.randomize_flow_field = true;
+        }
+        if let Some(output_directory_path) = &self.output_directory_path {
+            config.output_directory_path = output_directory_path.clone();
+        }
+        if self.threads.is_some() {
+            config.threads = self.threads;
+        }
+        if let Some(progress) = self.progress {
+            config.progress = progress;
+        }
+    }
+}
+
+impl Invocation {
+    pub fn configuration(&self) -> Result<Option<Configuration>, Box<dyn Error>> {
+        let config_file_path = match &self.config_file_path {
+            // 'init' writes the file, and 'sweep' reads a sweep file rather than a configuration.
+            Some(path)
+                if self.subcommand != Subcommand::Init && self.subcommand != Subcommand::Sweep =>
+            {
+                path
+            }
+            _ => {
+                return Ok(None);
+            }
+        };
+
+        let mut config = load_configuration(config_file_path)?;
+
+        self.overrides.apply(&mut config);
+        // After the overrides, so '--frames' cannot lift the preview limit.
+        if self.subcommand == Subcommand::Preview {
+            config.frames_number = config.frames_number.min(PREVIEW_FRAMES);
+        }
+
+        return Ok(Some(config));
+    }
+}
+
+pub fn command() -> Command {
+    let config = Arg::new("config")
+        .value_name("CONFIG")
+        .help("Path to the .json, .toml or .yaml configuration file")
+        .required(true);
+
+    return Command::new("fluid_simulation")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Simulates the advection of a mass distribution in a curl-noise flow field")
+        .subcommand_required(true)
+        .arg_required_else_help(true)
+        .subcommand(
+            Command::new("run")
+                .about("Runs the simulation and writes every frame")
+                .arg(config.clone())
+                .args(override_args()),
+        )
+        .subcommand(
+            Command::new("validate")
+                .about("Checks the configuration without running the simulation")
+                .arg(config.clone())
+                .args(override_args()),
+        )
+        .subcommand(
+            Command::new("preview")
+                .about(format!(
+                    "Renders the first {} frames as .png into the 'preview' subdirectory of the output directory",
+                    PREVIEW_FRAMES
+                ))
+                .arg(config.clone())
+                .args(override_args()),
+        )
+        .subcommand(
+            Command::new("sweep")
+                .about("Runs every combination of the swept values of a sweep file, each into its own subdirectory")
+                .arg(
+                    Arg::new("config")
+                        .value_name("SWEEP")
+                        .help("Path to the .json, .toml or .yaml sweep file")
+                        .required(true),
+                )
+                .args(override_args()),
+        )
+        .subcommand(
+            Command::new("contact-sheet")
+                .about("Builds a grid of labelled thumbnails of a run, or of the final frames of a sweep")
+                .arg(
+                    Arg::new("directory")
+                        .value_name("DIRECTORY")
+                        .help("Output directory of a run, or of a sweep (with its 'index.json')")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("every")
+                        .long("every")
+                        .value_name("N")
+                        .value_parser(value_parser!(usize))
+                        .help("Shows every Nth frame of a run (and the last one); by default up to 36 thumbnails"),
+                )
+                .arg(
+                    Arg::new("columns")
+                        .long("columns")
+                        .value_name("NUMBER")
+                        .value_parser(value_parser!(usize))
+                        .help("Thumbnails per row; by default the grid is about square"),
+                )
+                .arg(
+                    Arg::new("width")
+                        .long("width")
+                        .value_name("PIXELS")
+                        .value_parser(value_parser!(u32).range(16..))
+                        .default_value("240")
+                        .help("Width of a thumbnail"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .value_name("FILE")
+                        .help("Path of the sheet; by default 'contact_sheet.png' in the directory"),
+                ),
+        )
+        .subcommand(
+            Command::new("info")
+                .about("Prints the supported options and, for a configuration, the resulting grid and output sizes and the dynamic mode error")
+                .arg(config.required(false))
+                .args(override_args()),
+        )
+        .subcommand(
+            Command::new("init")
+                .about("Writes a commented example configuration file")
+                .arg(
+                    Arg::new("config")
+                        .value_name("CONFIG")
+                        .help("Path of the new configuration file; its extension selects JSON, TOML or YAML")
+                        .default_value("config.json"),
+                )
+                .arg(
+                    Arg::new("force")
+                        .long("force")
+                        .action(ArgAction::SetTrue)
+                        .help("Overwrites an existing file"),
+                ),
+        );
+}
+
+fn override_args() -> [Arg; 6] {
+    return [
+        Arg::new("frames")
+            .long("frames")
+            .value_name("NUMBER")
+            .value_parser(value_parser!(usize))
+            .help("Overrides 'frames_number'"),
+        Arg::new("resolution")
+            .long("resolution")
+            .value_name("RESOLUTION")
+            .value_parser(value_parser!(usize))
+            .help("Overrides 'target_resolution' (480, 720, 1080, 1440 or 2160)"),
+        Arg::new("seed")
+            .long("seed")
+            .value_name("SEED")
+            .value_parser(value_parser!(u64))
+            .help("Overrides 'seed' and enables 'randomize_flow_field'"),
+        Arg::new("output")
+            .long("output")
+            .value_name("DIRECTORY")
+            .help("Overrides 'output_directory_path'"),
+        Arg::new("threads")
+            .long("threads")
+            .value_name("NUMBER")
+            .value_parser(value_parser!(usize))
+            .help("Overrides 'threads'"),
+        Arg::new("progress")
+            .long("progress")
+            .value_name("MODE")
+            .value_parser(["bar", "json", "none"])
+            .help("Overrides 'progress' (json prints one line per frame on stdout)"),
+    ];
+}
+
+pub fn parse_arguments<I, T>(args: I) -> Result<Invocation, clap::Error>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString>,
+{
+    let mut args: Vec<OsString> = args.into_iter().map(|arg| arg.into()).collect();
+
+    // The original invocation, 'fluid_simulation config.json [--threads N]', means 'run'.
+    if let Some(first) = args.get(1).and_then(|arg| arg.to_str()) {
+        if first.starts_with('-') == false && SUBCOMMANDS.contains(&first) == false {
+            args.insert(1, OsString::from("run"));
+        }
+    }
+
+    let matches = command().try_get_matches_from(args)?;
+    let (name, matches) = match matches.subcommand() {
+        Some(subcommand) => subcommand,
+        None => unreachable!(),
+    };
+
+    return Ok(Invocation {
+        subcommand: match name {
+            "run" => Subcommand::Run,
+            "validate" => Subcommand::Validate,
+            "preview" => Subcommand::Preview,
+            "sweep" => Subcommand::Sweep,
+            "contact-sheet" => Subcommand::ContactSheet,
+            "info" => Subcommand::Info,
+            "init" => Subcommand::Init,
+            _ => unreachable!(),
+        },
+        config_file_path: value(matches, "config"),
+        overrides: overrides(matches),
+        force: value::<bool>(matches, "force").unwrap_or(false),
+        directory_path: value(matches, "directory"),
+        contact_sheet: ContactSheetOptions {
+            every: value(matches, "every"),
+            columns: value(matches, "columns"),
+            thumbnail_width: value(matches, "width").unwrap_or(240),
+            output_path: value(matches, "output"),
+        },
+    });
+}
+
+fn overrides(matches: &ArgMatches) -> Overrides {
+    return Overrides {
+        frames_number: value(matches, "frames"),
+        target_resolution: value(matches, "resolution"),
+        seed: value(matches, "seed"),
+        output_directory_path: value(matches, "output"),
+        threads: value(matches, "threads"),
+        progress: value::<String>(matches, "progress").map(|mode| match mode.as_str() {
+            "json" => ProgressMode::Json,
+            "none" => ProgressMode::None,
+            _ => ProgressMode::Bar,
+        }),
+    };
+}
+
+// Not every command defines every argument, and 'get_one' panics on undefined ones.
+fn value<T: Clone + Send + Sync + 'static>(matches: &ArgMatches, id: &str) -> Option<T> {
+    return matches.try_get_one::<T>(id).ok().flatten().cloned();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Invocation, clap::Error> {
+        return parse_arguments(std::iter::once("fluid_simulation").chain(args.iter().copied()));
+    }
+
+    #[test]
+    fn bare_configuration_path_means_run() {
+        let invocation = parse(&["config.json", "--threads", "4"]).unwrap();
+
+        assert_eq!(invocation.subcommand, Subcommand::Run);
+        assert_eq!(invocation.config_file_path.as_deref(), Some("config.json"));
+        assert_eq!(invocation.overrides.threads, Some(4));
+    }
+
+    #[test]
+    fn overrides_are_parsed() {
+        let invocation = parse(&[
+            "validate",
+            "config.json",
+            "--frames",
+            "12",
+            "--resolution",
+            "720",
+            "--seed",
+            "7",
+            "--output",
+            "out",
+        ])
+        .unwrap();
+
+        assert_eq!(invocation.subcommand, Subcommand::Validate);
+        assert_eq!(
+            invocation.overrides,
+            Overrides {
+                frames_number: Some(12),
+                target_resolution: Some(720),
+                seed: Some(7),
+                output_directory_path: Some(String::from("out")),
+                threads: None,
+                progress: None,
+            }
+        );
+    }
+
+    #[test]
+    fn progress_mode_is_parsed() {
+        let invocation = parse(&["run", "config.json", "--progress=json"]).unwrap();
+
+        assert_eq!(invocation.overrides.progress, Some(ProgressMode::Json));
+        assert_eq!(
+            parse(&["sweep", "sweep.json", "--progress", "none"])
+                .unwrap()
+                .overrides
+                .progress,
+            Some(ProgressMode::None)
+        );
+        assert!(parse(&["run", "config.json", "--progress", "xml"]).is_err());
+    }
+
+    #[test]
+    fn info_does_not_need_a_configuration() {
+        let invocation = parse(&["info"]).unwrap();
+
+        assert_eq!(invocation.subcommand, Subcommand::Info);
+        assert_eq!(invocation.config_file_path, None);
+        assert!(invocation.configuration().unwrap().is_none());
+    }
+
+    #[test]
+    fn init_writes_to_config_json_by_default() {
+        let invocation = parse(&["init"]).unwrap();
+
+        assert_eq!(invocation.subcommand, Subcommand::Init);
+        assert_eq!(invocation.config_file_path.as_deref(), Some("config.json"));
+        assert!(invocation.force == false);
+        assert!(invocation.configuration().unwrap().is_none());
+        assert!(parse(&["init", "new.json", "--force"]).unwrap().force);
+    }
+
+    #[test]
+    fn sweep_reads_a_sweep_file() {
+        let invocation = parse(&["sweep", "sweep.json", "--frames", "3"]).unwrap();
+
+        assert_eq!(invocation.subcommand, Subcommand::Sweep);
+        assert_eq!(invocation.config_file_path.as_deref(), Some("sweep.json"));
+        assert_eq!(invocation.overrides.frames_number, Some(3));
+        assert!(invocation.configuration().unwrap().is_none());
+    }
+
+    #[test]
+    fn contact_sheet_options_are_parsed() {
+        let invocation =
+            parse(&["contact-sheet", "frames", "--every", "10", "--width", "320"]).unwrap();
+
+        assert_eq!(invocation.subcommand, Subcommand::ContactSheet);
+        assert_eq!(invocation.directory_path.as_deref(), Some("frames"));
+        assert_eq!(
+            invocation.contact_sheet,
+            ContactSheetOptions {
+                every: Some(10),
+                columns: None,
+                thumbnail_width: 320,
+                output_path: None,
+            }
+        );
+        assert!(invocation.configuration().unwrap().is_none());
+        assert!(parse(&["contact-sheet", "frames", "--width", "4"]).is_err());
+    }
+
+    #[test]
+    fn malformed_arguments_are_rejected() {
+        assert!(parse(&["run"]).is_err());
+        assert!(parse(&["run", "config.json", "--resolution", "hd"]).is_err());
+        assert!(parse(&["run", "config.json", "--frames", "many"]).is_err());
+        assert!(parse(&["run", "config.json", "--colour", "red"]).is_err());
+    }
+
+    #[test]
+    fn help_and_version_are_available() {
+        assert_eq!(
+            parse(&["--help"]).unwrap_err().kind(),
+            clap::error::ErrorKind::DisplayHelp
+        );
+        assert_eq!(
+            parse(&["--version"]).unwrap_err().kind(),
+            clap::error::ErrorKind::DisplayVersion
+        );
+    }
+
+    #[test]
+    fn seed_override_enables_randomization() {
+        let mut config: Configuration = serde_json::from_str(
+            r#"{
+                "mass_distr_file_path": "mass.png",
+                "output_directory_path": "output",
+                "frames_number": 100,
+                "simulation_factor": 8,
+                "target_resolution": 1080,
+                "flow_field_scale": 300.0,
+                "dynamize_flow_field": true,
+                "randomize_flow_field": false
+            }"#,
+        )
+        .unwrap();
+
+        parse(&["run", "config.json", "--seed", "3", "--frames", "5"])
+            .unwrap()
+            .overrides
+            .apply(&mut config);
+
+        assert_eq!(config.seed, Some(3));
+        assert!(config.randomize_flow_field);
+        assert_eq!(config.frames_number, 5);
+        assert_eq!(config.output_directory_path, "output");
+    }
+
+    #[test]
+    fn preview_limits_the_overridden_frames() {
+        let config_file_path = std::env::temp_dir().join(format!(
+            "fluid_simulation_preview_{}.json",
+            std::process::id()
+        ));
+        std::fs::write(
+            &config_file_path,
+            r#"{"mass_distr_file_path": "mass.png", "frames_number": 2}"#,
+        )
+        .unwrap();
+        let config_file_path = config_file_path.to_str().unwrap();
+
+        let preview = parse(&["preview", config_file_path, "--frames", "100"]).unwrap();
+        let run = parse(&["run", config_file_path, "--frames", "100"]).unwrap();
+
+        assert_eq!(
+            preview.configuration().unwrap().unwrap().frames_number,
+            PREVIEW_FRAMES
+        );
+        assert_eq!(run.configuration().unwrap().unwrap().frames_number, 100);
+
+        std::fs::remove_file(config_file_path).unwrap();
+    }
+}
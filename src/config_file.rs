@@ -0,0 +1,615 @@
+use crate::error_kind::{classified, ErrorKind};
+use crate::utility::Configuration;
+
+use std::{error::Error, fmt, fs::OpenOptions, io::Write, path::Path};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConfigurationFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl fmt::Display for ConfigurationFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigurationFormat::Json => write!(f, "JSON"),
+            ConfigurationFormat::Toml => write!(f, "TOML"),
+            ConfigurationFormat::Yaml => write!(f, "YAML"),
+        }
+    }
+}
+
+pub const EXAMPLE_CONFIGURATION: &str = r##"// Example configuration of fluid_simulation.
+// Only 'mass_distr_file_path' is required; every other key shows its default
+// value and can be left out.
+{
+    // Graphic file with the initial mass distribution (.bmp, .png, .jpeg, ...).
+    // It is centred on the simulation grid and must not be larger than the grid.
+    "mass_distr_file_path": "mass_distr/mass.bmp",
+
+    // Directory for the result files.
+    "output_directory_path": "frames",
+
+    // Create the output directory (and its parents) when it does not exist.
+    "create_output_directory": true,
+
+    // What to do with frames of an earlier run in the output directory: "refuse" to
+    // start, "overwrite" them, or write into a new "timestamped" subdirectory run_YYYYMMDD_HHMMSS.
+    "output_policy": "refuse",
+
+    // Number of frames written to the output directory.
+    "frames_number": 1000,
+
+    // Number of simulation steps between two consecutive frames.
+    "simulation_factor": 8,
+
+    // Vertical resolution of the frames: 480, 720, 1080, 1440 or 2160.
+    "target_resolution": 1080,
+
+    // Size of the vortices of the flow field in pixels (at least 1.0).
+    "flow_field_scale": 300.0,
+
+    // Evolve the flow field during the simulation instead of keeping it static.
+    "dynamize_flow_field": true,
+
+    // Start from a random place of the noise instead of the origin.
+    "randomize_flow_field": true,
+
+    // Seed of 'randomize_flow_field' (a non-negative integer); null draws a new one on every run.
+    "seed": null,
+
+    // Velocity of the fluid: the "curl_noise" field itself, or "navier_stokes", a velocity
+    // field seeded from the curl noise that evolves with its own momentum (self-advection,
+    // viscosity and a pressure projection) and flows around obstacles.
+    "velocity_mode": "curl_noise",
+
+    // Kinematic viscosity of the "navier_stokes" mode, in cells^2 per step; 0.0 leaves
+    // only the numerical dissipation.
+    "viscosity": 0.0,
+
+    // Jacobi iterations of the pressure solve in the "navier_stokes" mode.
+    "pressure_iterations": 40,
+
+    // Strength of the curl noise added to the "navier_stokes" velocity on every step
+    // (it keeps the fluid stirred; the noise evolves when 'dynamize_flow_field' is set).
+    "noise_forcing": 0.0,
+
+    // Strength of the vorticity confinement force, which spins up the small vortices
+    // flattened by the numerical dissipation (about 0.02 - 0.1); 0.0 turns it off.
+    "vorticity_confinement": 0.0,
+
+    // Graphic file marking solid obstacles (bright pixels) for the "navier_stokes" mode,
+    // centred on the grid like the mass distribution; null for no obstacles.
+    "obstacle_mask_file_path": null,
+
+    // Boussinesq buoyancy of the "navier_stokes" mode: the velocity gains, on every step,
+    // 'gravity_direction' * ('density_weight' * mass - 'buoyancy' * temperature), so hot
+    // fluid rises and dense fluid sinks.
+    "buoyancy": 0.0,
+    "density_weight": 0.0,
+
+    // Direction of gravity [x, y] on the frame; y points down.
+    "gravity_direction": [0.0, 1.0],
+
+    // Fraction of the temperature lost on every step (0.0 - 1.0).
+    "cooling_rate": 0.0,
+
+    // Graphic file with the initial temperature, centred like the mass distribution;
+    // null starts with the temperature equal to the mass distribution.
+    "temperature_file_path": null,
+
+    // Number of tracer particles carried by the flow; 0 turns them off.
+    "particles_number": 0,
+
+    // Initial particle positions: "random", on a regular "grid", or drawn from the "mass".
+    "particle_seeding": "random",
+
+    // Integration of the particle paths: "rk2" (midpoint) or "rk4".
+    "particle_integrator": "rk2",
+
+    // Particles on the frames: "none", "dots" or fading "trails".
+    "particle_rendering": "dots",
+
+    // Draw the particles "over" the mass distribution or "alone" on a black background.
+    "particle_layer": "over",
+
+    // Fraction of the trail brightness kept from one frame to the next (0.0 - 1.0).
+    "particle_trail_fade": 0.9,
+
+    // Write the particle positions of every frame to particles.csv in the output directory.
+    "particle_csv": false,
+
+    // Exponential decay of the mass: on every step it is multiplied by exp(-decay_rate),
+    // and mass below 'decay_threshold' is removed; 0.0 keeps the mass forever.
+    "decay_rate": 0.0,
+    "decay_threshold": 0.0,
+
+    // Graphic file whose brightness (black 0.0 - white 1.0) scales 'decay_rate' in every
+    // pixel, centred like the mass distribution (no decay outside of it); null decays
+    // the mass everywhere at the same rate.
+    "decay_map_file_path": null,
+
+    // Gray-Scott reaction-diffusion: the mass distribution becomes the species V, which
+    // reacts with a second species U (U + 2V -> 3V) while both are carried by the flow.
+    "reaction_diffusion": false,
+
+    // Rate at which U is fed and rate at which V is removed (0.0 - 1.0); together they
+    // select the pattern (spots, stripes, coral, ...).
+    "feed_rate": 0.055,
+    "kill_rate": 0.062,
+
+    // Diffusion rates of U and V, in cells^2 per step (0.0 - 0.25).
+    "diffusion_u": 0.2,
+    "diffusion_v": 0.1,
+
+    // Reaction-diffusion steps per simulation step; more steps grow the pattern faster
+    // than the flow stirs it.
+    "reaction_steps": 1,
+
+    // Additional frames drawing the velocity field: "none", evenly spaced "streamlines" of
+    // the current field, or the "pathlines" of the fluid over the last 'pathline_length'
+    // steps (they differ from the streamlines when the field changes over time).
+    "velocity_rendering": "none",
+
+    // Format of the line frames: "png" or "svg".
+    "line_format": "png",
+
+    // Distance between neighbouring lines, in pixels (at least 2.0).
+    "line_spacing": 10.0,
+
+    // Line width in pixels.
+    "line_width": 1.0,
+
+    // Line colour as "#rrggbb" (on a black background).
+    "line_color": "#ffffff",
+
+    // Simulation steps covered by every pathline.
+    "pathline_length": 64,
+
+    // How the evolving flow field is computed: "full", "keyframes" or "coarse".
+    "dynamic_mode": "full",
+
+    // Steps between two exact flow fields in the "keyframes" mode.
+    "keyframe_interval": 8,
+
+    // Downsampling of the noise grid in the "coarse" mode.
+    "coarse_factor": 4,
+
+    // Solver threads; null uses all available cores.
+    "threads": null,
+
+    // Threads of a separate frame encoding pool; null shares the solver pool.
+    "encoder_threads": null,
+
+    // Background threads writing frames.
+    "encoder_workers": 2,
+
+    // Frames that may wait for encoding before the simulation waits for the encoders.
+    "encoder_queue_size": 4,
+
+    // Progress report of the run: a "bar", "json" (one line per frame on stdout with the
+    // frame, elapsed and remaining seconds and mass statistics) or "none".
+    "progress": "bar",
+
+    // Frame format: "png" (tone-mapped) or the raw float values as "exr", "tiff", "npy" or "npy_stack".
+    "output_format": "png",
+
+    // PNG compression: "fast", "default", "best", "huffman" or "rle".
+    "png_compression": "default",
+
+    // PNG filter: "adaptive", "none", "sub", "up", "avg" or "paeth".
+    "png_filter": "adaptive",
+
+    // Bits per PNG channel: 8 or 16.
+    "png_bit_depth": 8,
+
+    // PNG colour type: "gray" or "rgb".
+    "png_color": "gray",
+
+    // Logistic tone curve of the PNG frames: the mass (0.0 - 1.0 scaled to 0 - 255) at
+    // which the grey level is halfway, and the steepness of the curve.
+    "tone_midpoint": 170.0,
+    "tone_slope": 0.03,
+
+    // Keyframe tracks that change parameters during the run, e.g.
+    // [{"parameter": "flow_speed", "keyframes": [{"frame": 0, "value": 1.0, "interpolation": "ease"},
+    //                                            {"frame": 100, "value": 0.2}]}].
+    // Parameters: "flow_field_scale", "flow_speed", "evolution_speed", "tone_midpoint",
+    // "tone_slope", "decay_rate" and "noise_forcing"; interpolation towards the next
+    // keyframe: "linear" (default), "ease" or "step".
+    "parameter_tracks": []
+}
+"##;
+
+/// Format of a configuration file by its extension; other and missing extensions are read as
+/// JSON, like every configuration file before TOML and YAML were supported.
+pub fn configuration_format(configuration_file_path: &str) -> ConfigurationFormat {
+    let extension = Path::new(configuration_file_path)
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+
+    match extension.as_deref() {
+        Some("toml") => {
+            return ConfigurationFormat::Toml;
+        }
+        Some("yaml") | Some("yml") => {
+            return ConfigurationFormat::Yaml;
+        }
+        _ => {
+            return ConfigurationFormat::Json;
+        }
+    }
+}
+
+pub fn parse_configuration(
+    contents: &str,
+    format: ConfigurationFormat,
+) -> Result<Configuration, String> {
+    let result = match format {
+        ConfigurationFormat::Json => {
+            serde_json::from_str(&strip_comments(contents)).map_err(|error| error.to_string())
+        }
+        ConfigurationFormat::Toml => toml::from_str(contents).map_err(|error| error.to_string()),
+        ConfigurationFormat::Yaml => {
+            serde_yaml_ng::from_str(contents).map_err(|error| error.to_string())
+        }
+    };
+
+    return result.map_err(|error| explain_unknown_key(error.trim_end()));
+}
+
+/// Translates the commented JSON example line by line into the given format.
+pub fn example_configuration(format: ConfigurationFormat) -> String {
+    if format == ConfigurationFormat::Json {
+        return String::from(EXAMPLE_CONFIGURATION);
+    }
+
+    let mut example = String::new();
+
+    for line in EXAMPLE_CONFIGURATION.lines() {
+        let trimmed = line.trim();
+
+        if trimmed == "{" {
+            example.push('\n');
+            continue;
+        }
+        if trimmed == "}" {
+            continue;
+        }
+        if let Some(comment) = trimmed.strip_prefix("//") {
+            example.push_str(&format!("#{}\n", comment));
+            continue;
+        }
+
+        match trimmed.trim_end_matches(',').split_once(": ") {
+            Some((key, value)) => {
+                let key = key.trim_matches('"');
+
+                match (format, value) {
+                    // TOML has no null; an absent key means the same. A commented-out value
+                    // would not, so only the comment remains.
+                    (ConfigurationFormat::Toml, "null") => {
+                        example.push_str(&format!("# {} is left out, which means null\n", key))
+                    }
+                    (ConfigurationFormat::Toml, _) => {
+                        example.push_str(&format!("{} = {}\n", key, value))
+                    }
+                    _ => example.push_str(&format!("{}: {}\n", key, value)),
+                }
+            }
+            None => example.push('\n'),
+        }
+    }
+
+    return example;
+}
+
+pub fn write_example_configuration(
+    configuration_file_path: &str,
+    overwrite: bool,
+) -> Result<(), Box<dyn Error>> {
+    let example = example_configuration(configuration_format(configuration_file_path));
+    let mut options = OpenOptions::new();
+    options.write(true);
+
+    if overwrite == true {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+
+    let result = options
+        .open(configuration_file_path)
+        .and_then(|mut file| file.write_all(example.as_bytes()));
+
+    match result {
+        Ok(_) => {
+            return Ok(());
+        }
+        Err(error) => {
+            return Err(classified(
+                ErrorKind::Io,
+                format!(
+                    "Configuration File Error: cannot write file \'{}\'! Details: {}",
+                    configuration_file_path, error
+                ),
+            ));
+        }
+    }
+}
+
+/// Blanks out `//` and `/* */` comments outside of string literals.
+/// Line breaks are kept, so parser errors still point at the right line and column.
+pub fn strip_comments(contents: &str) -> String {
+    let mut stripped = String::with_capacity(contents.len());
+    let mut chars = contents.chars().peekable();
+    let mut in_string = false;
+
+    while let Some(c) = chars.next() {
+        if in_string {
+            stripped.push(c);
+            if c == '\\' {
+                if let Some(escaped) = chars.next() {
+                    stripped.push(escaped);
+                }
+            } else if c == '"' {
+                in_string = false;
+            }
+            continue;
+        }
+
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                stripped.push(c);
+            }
+            ('/', Some('/')) => {
+                while let Some(&next) = chars.peek() {
+                    if next == '\n' {
+                        break;
+                    }
+                    stripped.push(' ');
+                    chars.next();
+                }
+                stripped.push(' ');
+            }
+            ('/', Some('*')) => {
+                stripped.push_str("  ");
+                chars.next();
+
+                while let Some(next) = chars.next() {
+                    if next == '*' && chars.peek() == Some(&'/') {
+                        chars.next();
+                        stripped.push_str("  ");
+                        break;
+                    }
+                    stripped.push(if next == '\n' { '\n' } else { ' ' });
+                }
+            }
+            _ => {
+                stripped.push(c);
+            }
+        }
+    }
+
+    return stripped;
+}
+
+/// Appends a "did you mean" hint to serde's "unknown field `a`, expected one of `b`, `c`" errors.
+pub fn explain_unknown_key(message: &str) -> String {
+    let names: Vec<&str> = message.split('`').skip(1).step_by(2).collect();
+
+    if message.contains("unknown field") == false || names.len() < 2 {
+        return String::from(message);
+    }
+
+    let unknown = names[0];
+    let closest = names[1..]
+        .iter()
+        .map(|name| (edit_distance(unknown, name), *name))
+        .min();
+
+    match closest {
+        Some((distance, name)) if distance <= (unknown.len() / 3).max(2) => {
+            format!("{} (did you mean \'{}\'?)", message, name)
+        }
+        _ => String::from(message),
+    }
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, a) in a.chars().enumerate() {
+        current[0] = i + 1;
+
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + (a != *b) as usize;
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    return previous[b.len()];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comments_are_blanked_without_moving_the_rest() {
+        let contents = "{ // one\n  \"a\": \"//x\", /* two\nthree */ \"b\": 1 }";
+        let stripped = strip_comments(contents);
+
+        assert_eq!(stripped.len(), contents.len());
+        assert_eq!(
+            stripped,
+            "{       \n  \"a\": \"//x\",       \n         \"b\": 1 }"
+        );
+    }
+
+    #[test]
+    fn escaped_quotes_do_not_end_strings() {
+        let contents = r#"{"a": "\" // b"}"#;
+
+        assert_eq!(strip_comments(contents), contents);
+    }
+
+    #[test]
+    fn close_keys_are_suggested() {
+        let message = "unknown field `frame_number`, expected one of `frames_number`, `seed`";
+
+        assert!(explain_unknown_key(message).ends_with("(did you mean 'frames_number'?)"));
+        assert_eq!(
+            explain_unknown_key("unknown field `colour`, expected one of `frames_number`, `seed`"),
+            "unknown field `colour`, expected one of `frames_number`, `seed`"
+        );
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn example_configuration_lists_every_key() {
+        let config: Configuration =
+            serde_json::from_str(&strip_comments(EXAMPLE_CONFIGURATION)).unwrap();
+        assert_eq!(config.mass_distr_file_path, "mass_distr/mass.bmp");
+
+        let error = serde_json::from_str::<Configuration>("{\"unknown\": 0}").unwrap_err();
+        let message = error.to_string();
+        let keys: Vec<&str> = message.split('`').skip(3).step_by(2).collect();
+
+        assert!(keys.len() > 20);
+        for key in keys {
+            assert!(EXAMPLE_CONFIGURATION.contains(&format!("\"{}\":", key)));
+        }
+    }
+
+    #[test]
+    fn only_the_mass_distribution_is_required() {
+        let config: Configuration =
+            serde_json::from_str(r#"{"mass_distr_file_path": "mass.png"}"#).unwrap();
+        let example: Configuration =
+            serde_json::from_str(&strip_comments(EXAMPLE_CONFIGURATION)).unwrap();
+
+        assert_eq!(
+            format!("{:?}", config).replace("mass.png", "mass_distr/mass.bmp"),
+            format!("{:?}", example)
+        );
+        assert!(serde_json::from_str::<Configuration>("{}").is_err());
+    }
+
+    #[test]
+    fn format_follows_the_extension() {
+        assert_eq!(
+            configuration_format("a/config.JSON"),
+            ConfigurationFormat::Json
+        );
+        assert_eq!(
+            configuration_format("config.toml"),
+            ConfigurationFormat::Toml
+        );
+        assert_eq!(
+            configuration_format("config.yml"),
+            ConfigurationFormat::Yaml
+        );
+        assert_eq!(
+            configuration_format("config.cfg"),
+            ConfigurationFormat::Json
+        );
+        assert_eq!(configuration_format("config"), ConfigurationFormat::Json);
+    }
+
+    #[test]
+    fn examples_of_every_format_are_equivalent() {
+        let json = parse_configuration(EXAMPLE_CONFIGURATION, ConfigurationFormat::Json).unwrap();
+
+        for format in [ConfigurationFormat::Toml, ConfigurationFormat::Yaml] {
+            let config = parse_configuration(&example_configuration(format), format).unwrap();
+
+            assert_eq!(format!("{:?}", config), format!("{:?}", json));
+        }
+    }
+
+    #[test]
+    fn uncommented_examples_pass_the_check() {
+        let directory =
+            std::env::temp_dir().join(format!("fluid_simulation_example_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let mass_distr_file_path = directory.join("mass.png");
+        image::GrayImage::from_pixel(64, 48, image::Luma([128]))
+            .save(&mass_distr_file_path)
+            .unwrap();
+
+        for format in [
+            ConfigurationFormat::Json,
+            ConfigurationFormat::Toml,
+            ConfigurationFormat::Yaml,
+        ] {
+            // Commented-out values ('# key = value') become part of the configuration.
+            let example: String = example_configuration(format)
+                .lines()
+                .map(|line| match line.strip_prefix("# ") {
+                    Some(assignment)
+                        if assignment.split_once(" = ").is_some_and(|(key, _)| {
+                            key.chars().all(|c| c.is_ascii_lowercase() || c == '_')
+                        }) =>
+                    {
+                        format!("{}\n", assignment)
+                    }
+                    _ => format!("{}\n", line),
+                })
+                .collect::<String>()
+                .replace(
+                    "mass_distr/mass.bmp",
+                    mass_distr_file_path.to_str().unwrap(),
+                )
+                .replace(
+                    "\"frames\"",
+                    &format!("\"{}\"", directory.join("frames").display()),
+                );
+            let config = parse_configuration(&example, format).unwrap();
+
+            assert!(config.check().is_ok(), "{}: {:?}", format, config.check());
+        }
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn errors_keep_their_position_in_every_format() {
+        let cases = [
+            (
+                ConfigurationFormat::Json,
+                "{\n  \"mass_distr_file_path\": 1\n}",
+                "line 2",
+            ),
+            (
+                ConfigurationFormat::Toml,
+                "\nmass_distr_file_path = 1\n",
+                "line 2",
+            ),
+            (
+                ConfigurationFormat::Yaml,
+                "\nmass_distr_file_path: [1]\n",
+                "line 2",
+            ),
+        ];
+
+        for (format, contents, position) in cases {
+            let error = parse_configuration(contents, format).unwrap_err();
+
+            assert!(error.contains(position), "{}: {}", format, error);
+        }
+
+        let error = parse_configuration(
+            "mass_distr_file_path = \"mass.png\"\nframe_number = 3\n",
+            ConfigurationFormat::Toml,
+        )
+        .unwrap_err();
+        assert!(error.ends_with("(did you mean 'frames_number'?)"));
+    }
+}
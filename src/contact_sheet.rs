@@ -0,0 +1,577 @@
+use crate::error_kind::{classified, ErrorKind};
+
+use image::{imageops::FilterType, Rgb, RgbImage};
+use serde_json::Value;
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Thumbnails shown by default: the frame interval is chosen so a run fits this many.
+const DEFAULT_THUMBNAILS: usize = 36;
+
+const PADDING: u32 = 8;
+const BACKGROUND: Rgb<u8> = Rgb([32, 32, 32]);
+const LABEL_COLOR: Rgb<u8> = Rgb([255, 255, 255]);
+
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+const MAX_LABEL_LINES: usize = 3;
+
+/// 5x7 bitmap font; every row holds five pixels, the most significant bit on the left.
+/// Letters are drawn as capitals.
+#[rustfmt::skip]
+const FONT: [(char, [u8; 7]); 48] = [
+    ('a', [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+    ('b', [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110]),
+    ('c', [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110]),
+    ('d', [0b11110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b11110]),
+    ('e', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111]),
+    ('f', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000]),
+    ('g', [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111]),
+    ('h', [0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+    ('i', [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+    ('j', [0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100]),
+    ('k', [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001]),
+    ('l', [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111]),
+    ('m', [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001]),
+    ('n', [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001]),
+    ('o', [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110]),
+    ('p', [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000]),
+    ('q', [0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101]),
+    ('r', [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001]),
+    ('s', [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110]),
+    ('t', [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100]),
+    ('u', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110]),
+    ('v', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100]),
+    ('w', [0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010]),
+    ('x', [0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001]),
+    ('y', [0b10001, 0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100]),
+    ('z', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111]),
+    ('0', [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110]),
+    ('1', [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+    ('2', [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111]),
+    ('3', [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110]),
+    ('4', [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010]),
+    ('5', [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110]),
+    ('6', [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110]),
+    ('7', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000]),
+    ('8', [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110]),
+    ('9', [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100]),
+    (' ', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000]),
+    ('_', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111]),
+    ('-', [0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000]),
+    ('.', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100]),
+    (':', [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000]),
+    ('=', [0b00000, 0b00000, 0b11111, 0b00000, 0b11111, 0b00000, 0b00000]),
+    ('/', [0b00001, 0b00010, 0b00010, 0b00100, 0b01000, 0b01000, 0b10000]),
+    ('(', [0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010]),
+    (')', [0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000]),
+    (',', [0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b00100, 0b01000]),
+    ('+', [0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000]),
+    ('?', [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100]),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContactSheetOptions {
+    /// Interval between the frames of a run; by default a run fits 36 thumbnails.
+    pub every: Option<usize>,
+    /// Thumbnails per row; by default the grid is about square.
+    pub columns: Option<usize>,
+    pub thumbnail_width: u32,
+    /// By default 'contact_sheet.png' in the directory.
+    pub output_path: Option<String>,
+}
+
+impl Default for ContactSheetOptions {
+    fn default() -> Self {
+        return ContactSheetOptions {
+            every: None,
+            columns: None,
+            thumbnail_width: 240,
+            output_path: None,
+        };
+    }
+}
+
+/// A thumbnail with its label; `None` for a sweep variant without frames.
+struct Tile {
+    label: String,
+    frame_path: Option<String>,
+}
+
+/// Builds a contact sheet of a run directory (every Nth frame and the final one) or of a
+/// sweep directory with an 'index.json' (the final frame of every variant), and returns
+/// the path of the sheet.
+pub fn contact_sheet(
+    directory: &str,
+    options: &ContactSheetOptions,
+) -> Result<String, Box<dyn Error>> {
+    let index_path = Path::new(directory).join("index.json");
+
+    let tiles = if index_path.is_file() {
+        sweep_tiles(&index_path)?
+    } else {
+        run_tiles(directory, options.every)?
+    };
+
+    if tiles.iter().all(|tile| tile.frame_path.is_none()) {
+        return Err(classified(
+            ErrorKind::Input,
+            format!(
+                "Contact Sheet Error: no .png frames found in \'{}\'!",
+                directory
+            ),
+        ));
+    }
+
+    let mut thumbnails = Vec::new();
+    for tile in &tiles {
+        let thumbnail = match &tile.frame_path {
+            Some(frame_path) => match image::open(frame_path) {
+                Ok(frame) => Some(
+                    frame
+                        .resize(options.thumbnail_width, u32::MAX, FilterType::Triangle)
+                        .to_rgb8(),
+                ),
+                Err(error) => {
+                    return Err(classified(
+                        ErrorKind::Input,
+                        format!(
+                            "Contact Sheet Error: cannot open frame \'{}\'! Details: {}",
+                            frame_path, error
+                        ),
+                    ));
+                }
+            },
+            None => None,
+        };
+
+        thumbnails.push(thumbnail);
+    }
+
+    let sheet = compose(&tiles, &thumbnails, options);
+    let output_path = match &options.output_path {
+        Some(output_path) => output_path.clone(),
+        None => format!("{}/contact_sheet.png", directory),
+    };
+
+    match sheet.save(&output_path) {
+        Ok(_) => {
+            return Ok(output_path);
+        }
+        Err(error) => {
+            return Err(classified(
+                ErrorKind::Io,
+                format!(
+                    "Contact Sheet Error: cannot save \'{}\'! Details: {}",
+                    output_path, error
+                ),
+            ));
+        }
+    }
+}
+
+fn run_tiles(directory: &str, every: Option<usize>) -> Result<Vec<Tile>, Box<dyn Error>> {
+    let frames = png_frames(directory)?;
+    let every = every
+        .unwrap_or_else(|| frames.len().div_ceil(DEFAULT_THUMBNAILS))
+        .max(1);
+
+    return Ok(frames
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| index % every == 0 || *index == frames.len() - 1)
+        .map(|(_, (frame, frame_path))| Tile {
+            label: format!("frame {}", frame),
+            frame_path: Some(frame_path.clone()),
+        })
+        .collect());
+}
+
+fn sweep_tiles(index_path: &Path) -> Result<Vec<Tile>, Box<dyn Error>> {
+    let index: Value = match fs::read_to_string(index_path)
+        .map_err(|error| error.to_string())
+        .and_then(|index| serde_json::from_str(&index).map_err(|error| error.to_string()))
+    {
+        Ok(index) => index,
+        Err(error) => {
+            return Err(classified(
+                ErrorKind::Input,
+                format!(
+                    "Contact Sheet Error: cannot read the sweep index \'{}\'! Details: {}",
+                    index_path.display(),
+                    error
+                ),
+            ));
+        }
+    };
+
+    let variants = match index["variants"].as_array() {
+        Some(variants) => variants,
+        None => {
+            return Err(classified(
+                ErrorKind::Input,
+                format!(
+                    "Contact Sheet Error: \'{}\' does not list any variants!",
+                    index_path.display()
+                ),
+            ));
+        }
+    };
+
+    // The variant directories sit next to 'index.json'; relative paths are relative to
+    // where the sweep ran, so they are looked up in the index directory from the variant
+    // directory on (which keeps the 'run_...' subdirectory of a timestamped run).
+    let index_directory = index_path.parent().unwrap_or(Path::new(""));
+
+    return Ok(variants
+        .iter()
+        .map(|variant| {
+            let name = variant["name"].as_str().unwrap_or("?");
+            let frame_path = variant["output_directory_path"]
+                .as_str()
+                .map(Path::new)
+                .and_then(|directory| {
+                    if directory.is_absolute() {
+                        return Some(directory.to_path_buf());
+                    }
+
+                    let mut components = directory
+                        .components()
+                        .skip_while(|component| component.as_os_str() != name)
+                        .peekable();
+                    components.peek()?;
+
+                    return Some(index_directory.join(components.collect::<PathBuf>()));
+                })
+                .and_then(|directory| png_frames(&directory.to_string_lossy()).ok())
+                .and_then(|frames| frames.last().map(|(_, frame_path)| frame_path.clone()));
+
+            Tile {
+                label: String::from(name),
+                frame_path,
+            }
+        })
+        .collect());
+}
+
+/// The 'frame_N.png' files of a directory with their frame numbers, in frame order.
+fn png_frames(directory: &str) -> Result<Vec<(usize, String)>, Box<dyn Error>> {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(error) => {
+            return Err(classified(
+                ErrorKind::Input,
+                format!(
+                    "Contact Sheet Error: cannot read directory \'{}\'! Details: {}",
+                    directory, error
+                ),
+            ));
+        }
+    };
+
+    let mut frames: Vec<(usize, String)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let frame = name
+                .strip_prefix("frame_")?
+                .strip_suffix(".png")?
+                .parse::<usize>()
+                .ok()?;
+
+            Some((frame, entry.path().to_string_lossy().into_owned()))
+        })
+        .collect();
+    frames.sort();
+
+    return Ok(frames);
+}
+
+fn compose(
+    tiles: &[Tile],
+    thumbnails: &[Option<RgbImage>],
+    options: &ContactSheetOptions,
+) -> RgbImage {
+    let columns = options
+        .columns
+        .unwrap_or_else(|| (tiles.len() as f64).sqrt().ceil() as usize)
+        .clamp(1, tiles.len());
+    let rows = tiles.len().div_ceil(columns);
+
+    let tile_width = options.thumbnail_width;
+    let thumbnail_height = thumbnails
+        .iter()
+        .flatten()
+        .map(|thumbnail| thumbnail.height())
+        .max()
+        .unwrap_or(tile_width);
+    let scale = if tile_width >= 320 { 2 } else { 1 };
+    let characters = (tile_width / ((GLYPH_WIDTH + 1) * scale)).max(1) as usize;
+    let labels: Vec<Vec<String>> = tiles
+        .iter()
+        .zip(thumbnails)
+        .map(|(tile, thumbnail)| match thumbnail {
+            Some(_) => wrap_label(&tile.label, characters),
+            None => wrap_label(&format!("{} (no frames)", tile.label), characters),
+        })
+        .collect();
+    let line_height = (GLYPH_HEIGHT + 2) * scale;
+    let label_height =
+        labels.iter().map(Vec::len).max().unwrap_or(1) as u32 * line_height + 2 * scale;
+    let tile_height = thumbnail_height + label_height;
+
+    let mut sheet = RgbImage::from_pixel(
+        PADDING + columns as u32 * (tile_width + PADDING),
+        PADDING + rows as u32 * (tile_height + PADDING),
+        BACKGROUND,
+    );
+
+    for (index, (thumbnail, lines)) in thumbnails.iter().zip(&labels).enumerate() {
+        let x = PADDING + (index % columns) as u32 * (tile_width + PADDING);
+        let y = PADDING + (index / columns) as u32 * (tile_height + PADDING);
+
+        if let Some(thumbnail) = thumbnail {
+            // Thumbnails of other aspect ratios are centred in the tile.
+            image::imageops::overlay(
+                &mut sheet,
+                thumbnail,
+                (x + (tile_width - thumbnail.width()) / 2) as i64,
+                (y + (thumbnail_height - thumbnail.height()) / 2) as i64,
+            );
+        }
+
+        for (line, text) in lines.iter().enumerate() {
+            draw_label(
+                &mut sheet,
+                x,
+                y + thumbnail_height + 2 * scale + line as u32 * line_height,
+                tile_width,
+                text,
+                scale,
+            );
+        }
+    }
+
+    return sheet;
+}
+
+/// Splits a label into lines of `characters_per_line`; a label too long for three lines is cut.
+fn wrap_label(label: &str, characters_per_line: usize) -> Vec<String> {
+    let characters: Vec<char> = label.chars().collect();
+
+    return characters
+        .chunks(characters_per_line)
+        .take(MAX_LABEL_LINES)
+        .map(|line| line.iter().collect())
+        .collect();
+}
+
+/// Draws `text` with the bitmap font, cut to `max_width` pixels.
+pub fn draw_label(image: &mut RgbImage, x: u32, y: u32, max_width: u32, text: &str, scale: u32) {
+    let advance = (GLYPH_WIDTH + 1) * scale;
+    let characters = (max_width / advance) as usize;
+
+    for (index, character) in text.chars().take(characters).enumerate() {
+        let rows = glyph(character);
+        let glyph_x = x + index as u32 * advance;
+
+        for (row, bits) in rows.iter().enumerate() {
+            for column in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - column)) == 0 {
+                    continue;
+                }
+
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let (px, py) = (glyph_x + column * scale + dx, y + row as u32 * scale + dy);
+
+                        if px < image.width() && py < image.height() {
+                            image.put_pixel(px, py, LABEL_COLOR);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn glyph(character: char) -> [u8; 7] {
+    let character = character.to_ascii_lowercase();
+
+    return FONT
+        .iter()
+        .find(|(glyph, _)| *glyph == character)
+        .or_else(|| FONT.iter().find(|(glyph, _)| *glyph == '?'))
+        .map(|(_, rows)| *rows)
+        .unwrap_or([0; 7]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use image::{GrayImage, Luma};
+    use std::path::PathBuf;
+
+    fn directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!(
+            "fluid_simulation_contact_sheet_{}_{}",
+            name,
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        return directory;
+    }
+
+    fn write_frames(directory: &Path, frames: usize) {
+        for frame in 0..frames {
+            GrayImage::from_pixel(64, 48, Luma([(frame * 10) as u8]))
+                .save(directory.join(format!("frame_{}.png", frame)))
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn every_nth_frame_and_the_last_one_are_shown() {
+        let directory = directory("run");
+        write_frames(&directory, 11);
+
+        let tiles = run_tiles(&directory.to_string_lossy(), Some(4)).unwrap();
+        let labels: Vec<&str> = tiles.iter().map(|tile| tile.label.as_str()).collect();
+
+        assert_eq!(labels, vec!["frame 0", "frame 4", "frame 8", "frame 10"]);
+
+        let options = ContactSheetOptions {
+            every: Some(4),
+            columns: Some(3),
+            thumbnail_width: 48,
+            output_path: None,
+        };
+        let path = contact_sheet(&directory.to_string_lossy(), &options).unwrap();
+        let sheet = image::open(&path).unwrap();
+
+        // Two rows of three 48x36 thumbnails with 11-pixel label strips.
+        assert_eq!(
+            (sheet.width(), sheet.height()),
+            (8 + 3 * 56, 8 + 2 * (36 + 11 + 8))
+        );
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn sweep_variants_show_their_final_frame() {
+        let directory = directory("sweep");
+        let (first, second) = (directory.join("000_seed-1"), directory.join("001_seed-2"));
+        std::fs::create_dir_all(&first).unwrap();
+        write_frames(&first, 3);
+
+        let index = serde_json::json!({"variants": [
+            {"name": "000_seed-1", "output_directory_path": first},
+            {"name": "001_seed-2", "output_directory_path": second},
+        ]});
+        std::fs::write(directory.join("index.json"), index.to_string()).unwrap();
+
+        let tiles = sweep_tiles(&directory.join("index.json")).unwrap();
+
+        assert_eq!(tiles[0].label, "000_seed-1");
+        assert!(tiles[0]
+            .frame_path
+            .as_ref()
+            .unwrap()
+            .ends_with("frame_2.png"));
+        assert!(tiles[1].frame_path.is_none());
+        assert!(contact_sheet(
+            &directory.to_string_lossy(),
+            &ContactSheetOptions::default()
+        )
+        .is_ok());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn relative_variant_paths_are_found_next_to_the_index() {
+        let directory = directory("relative_sweep");
+        std::fs::create_dir_all(directory.join("000_seed-1")).unwrap();
+        write_frames(&directory.join("000_seed-1"), 2);
+
+        // As written by a sweep run from another working directory.
+        let index = serde_json::json!({"variants": [
+            {"name": "000_seed-1", "output_directory_path": "sweeps/000_seed-1"},
+        ]});
+        std::fs::write(directory.join("index.json"), index.to_string()).unwrap();
+
+        let tiles = sweep_tiles(&directory.join("index.json")).unwrap();
+
+        assert!(tiles[0]
+            .frame_path
+            .as_ref()
+            .unwrap()
+            .ends_with("frame_1.png"));
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn timestamped_variants_show_the_frames_of_their_run() {
+        let directory = directory("timestamped_sweep");
+        let run_directory = directory.join("000_seed-1").join("run_20261019_120000");
+        std::fs::create_dir_all(&run_directory).unwrap();
+        write_frames(&run_directory, 3);
+
+        let index = serde_json::json!({"variants": [
+            {"name": "000_seed-1", "output_directory_path": "sweeps/000_seed-1/run_20261019_120000"},
+        ]});
+        std::fs::write(directory.join("index.json"), index.to_string()).unwrap();
+
+        let tiles = sweep_tiles(&directory.join("index.json")).unwrap();
+
+        assert_eq!(
+            Path::new(tiles[0].frame_path.as_ref().unwrap()),
+            run_directory.join("frame_2.png")
+        );
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn labels_are_drawn_and_cut_to_the_tile() {
+        let mut image = RgbImage::from_pixel(40, 10, BACKGROUND);
+
+        draw_label(&mut image, 0, 0, 24, "1111111", 1);
+
+        // Four glyphs fit into 24 pixels; the stem of '1' is the middle column.
+        let lit: Vec<u32> = (0..40)
+            .filter(|x| *image.get_pixel(*x, 3) == LABEL_COLOR)
+            .collect();
+        assert_eq!(lit, vec![2, 8, 14, 20]);
+    }
+
+    #[test]
+    fn long_labels_are_wrapped() {
+        assert_eq!(
+            wrap_label("000_flow_field_scale-150.0", 10),
+            vec!["000_flow_f", "ield_scale", "-150.0"]
+        );
+        assert_eq!(wrap_label(&"x".repeat(50), 10).len(), MAX_LABEL_LINES);
+    }
+
+    #[test]
+    fn directories_without_frames_are_rejected() {
+        let directory = directory("empty");
+
+        let error = contact_sheet(
+            &directory.to_string_lossy(),
+            &ContactSheetOptions::default(),
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("no .png frames found"));
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
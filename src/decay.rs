@@ -0,0 +1,125 @@
+use rayon::prelude::*;
+
+/// Exponential decay of the mass, `mass *= exp(-rate)` on every step, where the rate can be
+/// scaled per cell by a decay map; mass below the threshold is removed.
+pub struct Decay {
+    decay_map: Option<Vec<f32>>,
+    retained: Vec<f32>,
+    threshold: f32,
+}
+
+impl Decay {
+    /// `decay_map` values (0.0 - 1.0) scale `decay_rate` in every cell.
+    pub fn new(
+        res_x: usize,
+        res_y: usize,
+        decay_rate: f32,
+        threshold: f32,
+        decay_map: Option<&Vec<f32>>,
+    ) -> Decay {
+        let mut decay = Decay {
+            decay_map: decay_map.map(|decay_map| {
+                decay_map
+                    .iter()
+                    .map(|scale| scale.clamp(0.0, 1.0))
+                    .collect()
+            }),
+            retained: vec![1.0; res_x * res_y],
+            threshold,
+        };
+        decay.set_decay_rate(decay_rate);
+
+        return decay;
+    }
+
+    pub fn set_decay_rate(&mut self, decay_rate: f32) {
+        match &self.decay_map {
+            Some(decay_map) => {
+                self.retained
+                    .par_iter_mut()
+                    .zip(decay_map.par_iter())
+                    .for_each(|(retained, scale)| *retained = (-decay_rate * scale).exp());
+            }
+            None => {
+                self.retained.fill((-decay_rate).exp());
+            }
+        }
+    }
+
+    pub fn apply(&self, mass_distr: &mut Vec<f32>) {
+        let threshold = self.threshold;
+
+        mass_distr
+            .par_iter_mut()
+            .zip(self.retained.par_iter())
+            .for_each(|(mass, retained)| {
+                *mass *= retained;
+
+                if *mass < threshold {
+                    *mass = 0.0;
+                }
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RES_X: usize = 8;
+    const RES_Y: usize = 4;
+
+    #[test]
+    fn mass_decays_exponentially() {
+        let decay = Decay::new(RES_X, RES_Y, 0.1, 0.0, None);
+        let mut mass_distr = vec![1.0; RES_X * RES_Y];
+
+        for _ in 0..10 {
+            decay.apply(&mut mass_distr);
+        }
+
+        assert!(mass_distr
+            .iter()
+            .all(|mass| (mass - (-1.0f32).exp()).abs() < 1e-5));
+    }
+
+    #[test]
+    fn mass_below_the_threshold_is_removed() {
+        let decay = Decay::new(RES_X, RES_Y, 0.0, 0.05, None);
+        let mut mass_distr: Vec<f32> = (0..RES_X * RES_Y)
+            .map(|index| index as f32 / 100.0)
+            .collect();
+
+        decay.apply(&mut mass_distr);
+
+        for (index, mass) in mass_distr.iter().enumerate() {
+            assert_eq!(*mass, if index < 5 { 0.0 } else { index as f32 / 100.0 });
+        }
+    }
+
+    #[test]
+    fn decay_map_selects_the_regions() {
+        let decay_map: Vec<f32> = (0..RES_X * RES_Y)
+            .map(|index| if index % RES_X < RES_X / 2 { 1.0 } else { 0.0 })
+            .collect();
+        let mut decay = Decay::new(RES_X, RES_Y, 0.5, 0.0, Some(&decay_map));
+        let mut mass_distr = vec![1.0; RES_X * RES_Y];
+
+        decay.apply(&mut mass_distr);
+
+        for (index, mass) in mass_distr.iter().enumerate() {
+            let expected = if index % RES_X < RES_X / 2 {
+                (-0.5f32).exp()
+            } else {
+                1.0
+            };
+            assert!((mass - expected).abs() < 1e-6);
+        }
+
+        let decayed = mass_distr.clone();
+        decay.set_decay_rate(0.0);
+        decay.apply(&mut mass_distr);
+
+        assert_eq!(mass_distr, decayed);
+    }
+}
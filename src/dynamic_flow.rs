@@ -0,0 +1,330 @@
+use crate::flow_field::{generate_coarse_flow_field, generate_flow_field, interpolate_flow_fields};
+use crate::simulate::simulate;
+use crate::utility::{DynamicMode, FlowField, Vec2D};
+
+use std::time::{Duration, Instant};
+
+/// Offset of the noise along its third dimension per step, at an evolution speed of 1.0.
+const EVOLUTION_STEP: f64 = 0.3;
+
+pub struct DynamicFlowField {
+    mode: DynamicMode,
+    res_x: usize,
+    res_y: usize,
+    scale: f64,
+    offset_x: f64,
+    offset_y: f64,
+    offset_z: f64,
+    evolution_offsets: Vec<f64>,
+    evolution_speed: f64,
+    keyframe_interval: usize,
+    coarse_factor: usize,
+    noise_buffer: Vec<f32>,
+    coarse_noise_buffer: Vec<f32>,
+    keyframe_from: FlowField,
+    keyframe_to: FlowField,
+    keyframe_index: Option<usize>,
+    coarse_flow_field: FlowField,
+}
+
+pub struct DynamicModeReport {
+    pub speed_up: f64,
+    pub flow_field_rms_error: f32,
+    pub flow_field_max_error: f32,
+    pub mass_distr_rms_error: f32,
+}
+
+impl DynamicFlowField {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        mode: DynamicMode,
+        res_x: usize,
+        res_y: usize,
+        scale: f64,
+        offset_x: f64,
+        offset_y: f64,
+        offset_z: f64,
+        keyframe_interval: usize,
+        coarse_factor: usize,
+    ) -> DynamicFlowField {
+        let keyframe_len = match mode {
+            DynamicMode::Keyframes => res_x * res_y,
+            _ => 0,
+        };
+
+        return DynamicFlowField {
+            mode,
+            res_x,
+            res_y,
+            scale,
+            offset_x,
+            offset_y,
+            offset_z,
+            evolution_offsets: vec![0.0],
+            evolution_speed: 1.0,
+            keyframe_interval,
+            coarse_factor,
+            noise_buffer: Vec::new(),
+            coarse_noise_buffer: Vec::new(),
+            keyframe_from: FlowField::new(keyframe_len),
+            keyframe_to: FlowField::new(keyframe_len),
+            keyframe_index: None,
+            coarse_flow_field: FlowField::new(0),
+        };
+    }
+
+    /// Changes the noise scale; the cached keyframes are generated again.
+    pub fn set_scale(&mut self, scale: f64) {
+        if self.scale != scale {
+            self.scale = scale;
+            self.keyframe_index = None;
+        }
+    }
+
+    /// Sets the evolution speed of every step (1.0 is the usual speed); steps past the end
+    /// keep the last speed.
+    pub fn set_evolution_speeds(&mut self, speeds: &[f64]) {
+        self.evolution_offsets = vec![0.0];
+
+        for speed in speeds {
+            let offset = self.evolution_offsets[self.evolution_offsets.len() - 1];
+            self.evolution_offsets.push(offset + speed * EVOLUTION_STEP);
+        }
+
+        self.evolution_speed = speeds.last().copied().unwrap_or(1.0);
+        self.keyframe_index = None;
+    }
+
+    pub fn update(&mut self, flow_field: &mut FlowField, step: usize) {
+        let offset_z = self.step_offset_z(step);
+
+        match self.mode {
+            DynamicMode::Full => {
+                generate_flow_field(
+                    flow_field,
+                    &mut self.noise_buffer,
+                    self.res_x,
+                    self.res_y,
+                    self.scale,
+                    self.offset_x,
+                    self.offset_y,
+                    offset_z,
+                );
+            }
+            DynamicMode::Keyframes => {
+                let keyframe_index = step / self.keyframe_interval;
+
+                if self.keyframe_index != Some(keyframe_index) {
+                    if self.keyframe_index == Some(keyframe_index.wrapping_sub(1)) {
+                        std::mem::swap(&mut self.keyframe_from, &mut self.keyframe_to);
+                    } else {
+                        self.generate_keyframe(keyframe_index, false);
+                    }
+
+                    self.generate_keyframe(keyframe_index + 1, true);
+                    self.keyframe_index = Some(keyframe_index);
+                }
+
+                interpolate_flow_fields(
+                    flow_field,
+                    &self.keyframe_from,
+                    &self.keyframe_to,
+                    (step % self.keyframe_interval) as f32 / self.keyframe_interval as f32,
+                );
+            }
+            DynamicMode::Coarse => {
+                generate_coarse_flow_field(
+                    flow_field,
+                    &mut self.coarse_noise_buffer,
+                    &mut self.coarse_flow_field,
+                    self.res_x,
+                    self.res_y,
+                    self.coarse_factor,
+                    self.scale,
+                    self.offset_x,
+                    self.offset_y,
+                    offset_z,
+                );
+            }
+        }
+    }
+
+    pub fn evaluate(&mut self, mass_distr: &[f32], steps: usize) -> DynamicModeReport {
+        let mut reference = DynamicFlowField::new(
+            DynamicMode::Full,
+            self.res_x,
+            self.res_y,
+            self.scale,
+            self.offset_x,
+            self.offset_y,
+            self.offset_z,
+            self.keyframe_interval,
+            self.coarse_factor,
+        );
+
+        reference.evolution_offsets = self.evolution_offsets.clone();
+        reference.evolution_speed = self.evolution_speed;
+
+        let mut flow_field = FlowField::new(self.res_x * self.res_y);
+        let mut reference_flow_field = FlowField::new(self.res_x * self.res_y);
+        let mut mass = mass_distr.to_vec();
+        let mut reference_mass = mass_distr.to_vec();
+        let mut mass_buffer = vec![0.0; self.res_x * self.res_y];
+        let mut reference_mass_buffer = vec![0.0; self.res_x * self.res_y];
+
+        let mut time = Duration::ZERO;
+        let mut reference_time = Duration::ZERO;
+        let mut squared_error_sum: f64 = 0.0;
+        let mut max_error: f32 = 0.0;
+
+        for step in 0..steps {
+            let start = Instant::now();
+            self.update(&mut flow_field, step);
+            time += start.elapsed();
+
+            let start = Instant::now();
+            reference.update(&mut reference_flow_field, step);
+            reference_time += start.elapsed();
+
+            for (value, reference_value) in flow_field.iter().zip(reference_flow_field.iter()) {
+                let error = Vec2D {
+                    x: value.x - reference_value.x,
+                    y: value.y - reference_value.y,
+                }
+                .length();
+
+                squared_error_sum += (error * error) as f64;
+                max_error = max_error.max(error);
+            }
+
+            simulate(
+                &flow_field,
+                &mut mass,
+                &mut mass_buffer,
+                self.res_x,
+                self.res_y,
+            );
+            simulate(
+                &reference_flow_field,
+                &mut reference_mass,
+                &mut reference_mass_buffer,
+                self.res_x,
+                self.res_y,
+            );
+        }
+
+        let mass_squared_error_sum: f64 = mass
+            .iter()
+            .zip(reference_mass.iter())
+            .map(|(value, reference_value)| ((value - reference_value) as f64).powi(2))
+            .sum();
+
+        self.keyframe_index = None;
+
+        return DynamicModeReport {
+            speed_up: reference_time.as_secs_f64() / time.as_secs_f64().max(f64::EPSILON),
+            flow_field_rms_error: (squared_error_sum / (steps * flow_field.x.len()) as f64).sqrt()
+                as f32,
+            flow_field_max_error: max_error,
+            mass_distr_rms_error: (mass_squared_error_sum / mass.len() as f64).sqrt() as f32,
+        };
+    }
+
+    fn step_offset_z(&self, step: usize) -> f64 {
+        let last = self.evolution_offsets.len() - 1;
+        let offset = match self.evolution_offsets.get(step) {
+            Some(offset) => *offset,
+            None => {
+                self.evolution_offsets[last]
+                    + (step - last) as f64 * self.evolution_speed * EVOLUTION_STEP
+            }
+        };
+
+        return self.offset_z + offset;
+    }
+
+    fn generate_keyframe(&mut self, keyframe_index: usize, upper: bool) {
+        let offset_z = self.step_offset_z(keyframe_index * self.keyframe_interval);
+        let keyframe = if upper {
+            &mut self.keyframe_to
+        } else {
+            &mut self.keyframe_from
+        };
+
+        generate_flow_field(
+            keyframe,
+            &mut self.noise_buffer,
+            self.res_x,
+            self.res_y,
+            self.scale,
+            self.offset_x,
+            self.offset_y,
+            offset_z,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RES_X: usize = 64;
+    const RES_Y: usize = 48;
+
+    fn dynamic_flow_field(mode: DynamicMode) -> DynamicFlowField {
+        return DynamicFlowField::new(mode, RES_X, RES_Y, 20.0, 0.0, 0.0, 0.0, 4, 4);
+    }
+
+    #[test]
+    fn keyframes_match_full_regeneration_on_key_steps() {
+        let mut full = dynamic_flow_field(DynamicMode::Full);
+        let mut keyframes = dynamic_flow_field(DynamicMode::Keyframes);
+        let mut full_flow_field = FlowField::new(RES_X * RES_Y);
+        let mut keyframes_flow_field = FlowField::new(RES_X * RES_Y);
+
+        for step in 0..13 {
+            keyframes.update(&mut keyframes_flow_field, step);
+
+            if step % 4 == 0 {
+                full.update(&mut full_flow_field, step);
+
+                for (a, b) in full_flow_field.iter().zip(keyframes_flow_field.iter()) {
+                    assert!((a.x - b.x).abs() < 1e-6 && (a.y - b.y).abs() < 1e-6);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn evolution_speed_scales_the_noise_offset() {
+        let mut full = dynamic_flow_field(DynamicMode::Full);
+        let mut halved = dynamic_flow_field(DynamicMode::Full);
+        let mut full_flow_field = FlowField::new(RES_X * RES_Y);
+        let mut halved_flow_field = FlowField::new(RES_X * RES_Y);
+
+        // Half speed for 8 steps, then the field stops evolving.
+        halved.set_evolution_speeds(&[0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.0]);
+
+        full.update(&mut full_flow_field, 4);
+        for step in [8, 12] {
+            halved.update(&mut halved_flow_field, step);
+
+            for (a, b) in full_flow_field.iter().zip(halved_flow_field.iter()) {
+                assert!((a.x - b.x).abs() < 1e-6 && (a.y - b.y).abs() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn evaluation_reports_errors_against_full_regeneration() {
+        let mass_distr = vec![0.5; RES_X * RES_Y];
+
+        let report = dynamic_flow_field(DynamicMode::Full).evaluate(&mass_distr, 4);
+        assert_eq!(report.flow_field_rms_error, 0.0);
+        assert_eq!(report.mass_distr_rms_error, 0.0);
+
+        let report = dynamic_flow_field(DynamicMode::Coarse).evaluate(&mass_distr, 4);
+        assert!(report.flow_field_rms_error > 0.0);
+        assert!(report.flow_field_max_error >= report.flow_field_rms_error);
+    }
+}
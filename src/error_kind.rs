@@ -0,0 +1,121 @@
+use crate::utility::ConfigurationErrors;
+
+use std::{error::Error, fmt};
+
+/// What went wrong, for the exit status of the program; usage errors of the command line
+/// exit with 2 (from clap), and unclassified errors with 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorKind {
+    Configuration,
+    Input,
+    Io,
+    Numerical,
+}
+
+#[derive(Debug)]
+pub struct ClassifiedError {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl ErrorKind {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            ErrorKind::Configuration => 3,
+            ErrorKind::Input => 4,
+            ErrorKind::Io => 5,
+            ErrorKind::Numerical => 6,
+        }
+    }
+
+    pub fn from_exit_code(code: u8) -> Option<ErrorKind> {
+        return [
+            ErrorKind::Configuration,
+            ErrorKind::Input,
+            ErrorKind::Io,
+            ErrorKind::Numerical,
+        ]
+        .into_iter()
+        .find(|kind| kind.exit_code() == code);
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::Configuration => write!(f, "configuration"),
+            ErrorKind::Input => write!(f, "input"),
+            ErrorKind::Io => write!(f, "io"),
+            ErrorKind::Numerical => write!(f, "numerical"),
+        }
+    }
+}
+
+impl fmt::Display for ClassifiedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for ClassifiedError {}
+
+pub fn classified(kind: ErrorKind, message: String) -> Box<dyn Error> {
+    return Box::new(ClassifiedError { kind, message });
+}
+
+/// Kind of an error returned by the commands; `None` when it cannot be told.
+pub fn error_kind(error: &(dyn Error + 'static)) -> Option<ErrorKind> {
+    if let Some(error) = error.downcast_ref::<ClassifiedError>() {
+        return Some(error.kind);
+    }
+    if error.is::<ConfigurationErrors>() {
+        return Some(ErrorKind::Configuration);
+    }
+    if error.is::<image::ImageError>() {
+        return Some(ErrorKind::Input);
+    }
+    if error.is::<std::io::Error>() {
+        return Some(ErrorKind::Io);
+    }
+
+    return None;
+}
+
+pub fn exit_code(error: &(dyn Error + 'static)) -> u8 {
+    return error_kind(error).map_or(1, |kind| kind.exit_code());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_kind_has_its_own_exit_code() {
+        let codes: Vec<u8> = [
+            ErrorKind::Configuration,
+            ErrorKind::Input,
+            ErrorKind::Io,
+            ErrorKind::Numerical,
+        ]
+        .iter()
+        .map(|kind| exit_code(classified(*kind, String::from("error")).as_ref()))
+        .collect();
+
+        assert_eq!(codes, vec![3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn library_errors_are_classified() {
+        let configuration: Box<dyn Error> = Box::new(ConfigurationErrors {
+            problems: Vec::new(),
+        });
+        let io: Box<dyn Error> = Box::new(std::io::Error::other("disk full"));
+        let input: Box<dyn Error> = Box::new(image::open("missing.png").unwrap_err());
+        let other: Box<dyn Error> = "Thread Pool Error: no threads!".into();
+
+        assert_eq!(exit_code(configuration.as_ref()), 3);
+        assert_eq!(exit_code(input.as_ref()), 4);
+        assert_eq!(exit_code(io.as_ref()), 5);
+        assert_eq!(exit_code(other.as_ref()), 1);
+    }
+}
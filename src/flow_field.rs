@@ -1,10 +1,11 @@
-use crate::utility::Vec2D;
+use crate::utility::{FlowField, Vec2D};
 
 use noise::{NoiseFn, SuperSimplex};
 use rayon::prelude::*;
 
+#[allow(clippy::too_many_arguments)]
 pub fn generate_flow_field(
-    flow_field: &mut Vec<Vec2D>,
+    flow_field: &mut FlowField,
     noise_buffer: &mut Vec<f32>,
     res_x: usize,
     res_y: usize,
@@ -14,7 +15,8 @@ pub fn generate_flow_field(
     offset_z: f64,
 ) {
     let noise = SuperSimplex::new();
-    let mut max_magnitude: f32 = f32::MIN;
+
+    noise_buffer.resize(res_x * res_y, 0.0);
 
     noise_buffer
         .par_chunks_mut(res_x)
@@ -29,27 +31,280 @@ pub fn generate_flow_field(
             }
         });
 
-    flow_field
+    let noise_buffer = &*noise_buffer;
+
+    let max_magnitude = flow_field
+        .x
         .par_chunks_mut(res_x)
+        .zip(flow_field.y.par_chunks_mut(res_x))
+        .enumerate()
+        .map(|(y, (chunk_x, chunk_y))| {
+            let mut max_magnitude: f32 = f32::MIN;
+
+            if y == 0 || y == res_y - 1 {
+                chunk_x.fill(0.0);
+                chunk_y.fill(0.0);
+                return max_magnitude;
+            }
+
+            chunk_x[0] = 0.0;
+            chunk_y[0] = 0.0;
+            chunk_x[res_x - 1] = 0.0;
+            chunk_y[res_x - 1] = 0.0;
+
+            for x in 1..res_x - 1 {
+                let value = Vec2D {
+                    x: noise_buffer[(y + 1) * res_x + x] - noise_buffer[(y - 1) * res_x + x],
+                    y: -(noise_buffer[y * res_x + x + 1] - noise_buffer[y * res_x + x - 1]),
+                };
+
+                max_magnitude = max_magnitude.max(value.length());
+                chunk_x[x] = value.x;
+                chunk_y[x] = value.y;
+            }
+
+            return max_magnitude;
+        })
+        .reduce(|| f32::MIN, f32::max);
+
+    normalise_flow_field(flow_field, max_magnitude);
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn generate_coarse_flow_field(
+    flow_field: &mut FlowField,
+    coarse_noise_buffer: &mut Vec<f32>,
+    coarse_flow_field: &mut FlowField,
+    res_x: usize,
+    res_y: usize,
+    factor: usize,
+    scale: f64,
+    offset_x: f64,
+    offset_y: f64,
+    offset_z: f64,
+) {
+    let noise = SuperSimplex::new();
+    let coarse_res_x = (res_x - 1).div_ceil(factor) + 1;
+    let coarse_res_y = (res_y - 1).div_ceil(factor) + 1;
+    let noise_res_x = coarse_res_x + 2;
+
+    coarse_noise_buffer.resize(noise_res_x * (coarse_res_y + 2), 0.0);
+    coarse_flow_field.x.resize(coarse_res_x * coarse_res_y, 0.0);
+    coarse_flow_field.y.resize(coarse_res_x * coarse_res_y, 0.0);
+
+    coarse_noise_buffer
+        .par_chunks_mut(noise_res_x)
+        .enumerate()
+        .for_each(|(y, chunk)| {
+            for (x, value) in chunk.iter_mut().enumerate() {
+                *value = noise.get([
+                    ((x as f64 - 1.0) * factor as f64 - offset_x) / scale,
+                    ((y as f64 - 1.0) * factor as f64 - offset_y) / scale,
+                    offset_z / scale,
+                ]) as f32;
+            }
+        });
+
+    coarse_flow_field
+        .x
+        .par_chunks_mut(coarse_res_x)
+        .zip(coarse_flow_field.y.par_chunks_mut(coarse_res_x))
+        .enumerate()
+        .for_each(|(y, (chunk_x, chunk_y))| {
+            for x in 0..coarse_res_x {
+                chunk_x[x] = (coarse_noise_buffer[(y + 2) * noise_res_x + x + 1]
+                    - coarse_noise_buffer[y * noise_res_x + x + 1])
+                    / factor as f32;
+                chunk_y[x] = -(coarse_noise_buffer[(y + 1) * noise_res_x + x + 2]
+                    - coarse_noise_buffer[(y + 1) * noise_res_x + x])
+                    / factor as f32;
+            }
+        });
+
+    let coarse_flow_field = &*coarse_flow_field;
+
+    let max_magnitude = flow_field
+        .x
+        .par_chunks_mut(res_x)
+        .zip(flow_field.y.par_chunks_mut(res_x))
         .enumerate()
         .skip(1)
         .take(res_y - 2)
-        .for_each(|(y, chunk)| {
-            for (x, value) in chunk.iter_mut().enumerate().skip(1).take(res_x - 2) {
-                *value = Vec2D {
-                    x: (noise_buffer[(y + 1) * res_x + x] - noise_buffer[(y - 1) * res_x + x])
-                        as f32,
-                    y: -(noise_buffer[y * res_x + x + 1] - noise_buffer[y * res_x + x - 1]) as f32,
+        .map(|(y, (chunk_x, chunk_y))| {
+            let coarse_y = y / factor;
+            let t_y = (y % factor) as f32 / factor as f32;
+            let mut max_magnitude: f32 = f32::MIN;
+
+            for x in 1..res_x - 1 {
+                let coarse_x = x / factor;
+                let t_x = (x % factor) as f32 / factor as f32;
+
+                let top_left = coarse_flow_field.get(coarse_y * coarse_res_x + coarse_x);
+                let top_right = coarse_flow_field.get(coarse_y * coarse_res_x + coarse_x + 1);
+                let bottom_left = coarse_flow_field.get((coarse_y + 1) * coarse_res_x + coarse_x);
+                let bottom_right =
+                    coarse_flow_field.get((coarse_y + 1) * coarse_res_x + coarse_x + 1);
+
+                let value = Vec2D {
+                    x: (top_left.x * (1.0 - t_x) + top_right.x * t_x) * (1.0 - t_y)
+                        + (bottom_left.x * (1.0 - t_x) + bottom_right.x * t_x) * t_y,
+                    y: (top_left.y * (1.0 - t_x) + top_right.y * t_x) * (1.0 - t_y)
+                        + (bottom_left.y * (1.0 - t_x) + bottom_right.y * t_x) * t_y,
                 };
+
+                max_magnitude = max_magnitude.max(value.length());
+                chunk_x[x] = value.x;
+                chunk_y[x] = value.y;
             }
+
+            return max_magnitude;
+        })
+        .reduce(|| f32::MIN, f32::max);
+
+    normalise_flow_field(flow_field, max_magnitude);
+}
+
+pub fn interpolate_flow_fields(
+    flow_field: &mut FlowField,
+    flow_field_from: &FlowField,
+    flow_field_to: &FlowField,
+    t: f32,
+) {
+    for (values, from, to) in [
+        (&mut flow_field.x, &flow_field_from.x, &flow_field_to.x),
+        (&mut flow_field.y, &flow_field_from.y, &flow_field_to.y),
+    ] {
+        values
+            .par_iter_mut()
+            .zip(from.par_iter())
+            .zip(to.par_iter())
+            .for_each(|((value, from), to)| {
+                *value = from * (1.0 - t) + to * t;
+            });
+    }
+}
+
+fn normalise_flow_field(flow_field: &mut FlowField, max_magnitude: f32) {
+    flow_field
+        .x
+        .par_iter_mut()
+        .chain(flow_field.y.par_iter_mut())
+        .for_each(|value| {
+            *value /= max_magnitude;
         });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RES_X: usize = 96;
+    const RES_Y: usize = 64;
+
+    fn flow_field(offset_z: f64) -> FlowField {
+        let mut flow_field = FlowField::new(RES_X * RES_Y);
+        flow_field.x.fill(1.0);
+        flow_field.y.fill(1.0);
+        generate_flow_field(
+            &mut flow_field,
+            &mut Vec::new(),
+            RES_X,
+            RES_Y,
+            30.0,
+            1.0,
+            2.0,
+            offset_z,
+        );
 
-    for value in flow_field.iter() {
-        max_magnitude = max_magnitude.max(value.length());
+        return flow_field;
     }
 
-    flow_field.par_iter_mut().for_each(|value| {
-        value.x = value.x / max_magnitude;
-        value.y = value.y / max_magnitude;
-    });
+    #[test]
+    fn flow_field_is_normalised() {
+        let max_magnitude = flow_field(0.0)
+            .iter()
+            .map(|value| value.length())
+            .fold(0.0, f32::max);
+
+        assert!((max_magnitude - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn flow_field_border_is_zero() {
+        let flow_field = flow_field(0.0);
+
+        for (index, value) in flow_field.iter().enumerate() {
+            let (x, y) = (index % RES_X, index / RES_X);
+
+            if x == 0 || y == 0 || x == RES_X - 1 || y == RES_Y - 1 {
+                assert_eq!((value.x, value.y), (0.0, 0.0));
+            }
+        }
+    }
+
+    #[test]
+    fn flow_field_is_the_curl_of_the_noise() {
+        let noise = SuperSimplex::new();
+        let flow_field = flow_field(0.0);
+        let sample = |x: usize, y: usize| {
+            noise.get([(x as f64 - 1.0) / 30.0, (y as f64 - 2.0) / 30.0, 0.0]) as f32
+        };
+        let (x, y) = (40, 30);
+        let curl = Vec2D {
+            x: sample(x, y + 1) - sample(x, y - 1),
+            y: -(sample(x + 1, y) - sample(x - 1, y)),
+        };
+        let value = flow_field.get(y * RES_X + x);
+
+        assert!((value.x * curl.y - value.y * curl.x).abs() < 1e-6);
+        assert!(value.x * curl.x + value.y * curl.y > 0.0);
+    }
+
+    #[test]
+    fn coarse_flow_field_approximates_the_full_one() {
+        let full = flow_field(0.0);
+        let mut coarse = FlowField::new(RES_X * RES_Y);
+        let mut coarse_noise_buffer = Vec::new();
+        let mut coarse_flow_field = FlowField::new(0);
+
+        generate_coarse_flow_field(
+            &mut coarse,
+            &mut coarse_noise_buffer,
+            &mut coarse_flow_field,
+            RES_X,
+            RES_Y,
+            4,
+            30.0,
+            1.0,
+            2.0,
+            0.0,
+        );
+
+        let squared_error_sum: f32 = full
+            .iter()
+            .zip(coarse.iter())
+            .map(|(a, b)| (a.x - b.x).powi(2) + (a.y - b.y).powi(2))
+            .sum();
+
+        assert!((squared_error_sum / full.x.len() as f32).sqrt() < 0.05);
+    }
+
+    #[test]
+    fn interpolation_hits_both_endpoints() {
+        let from = flow_field(0.0);
+        let to = flow_field(3.0);
+        let mut interpolated = FlowField::new(RES_X * RES_Y);
+
+        interpolate_flow_fields(&mut interpolated, &from, &to, 0.0);
+        assert!(interpolated
+            .iter()
+            .zip(from.iter())
+            .all(|(a, b)| a.x == b.x && a.y == b.y));
+
+        interpolate_flow_fields(&mut interpolated, &from, &to, 1.0);
+        assert!(interpolated
+            .iter()
+            .zip(to.iter())
+            .all(|(a, b)| a.x == b.x && a.y == b.y));
+    }
 }
@@ -0,0 +1,466 @@
+use crate::error_kind::{classified, ErrorKind};
+use crate::save_frame::{save_frame, PngOptions, ToneCurve};
+
+use exr::prelude::{Image, SpecificChannels, WritableImage};
+use serde::Deserialize;
+use std::{
+    error::Error,
+    fmt,
+    fs::File,
+    io::{BufWriter, Seek, SeekFrom, Write},
+    sync::Mutex,
+};
+use tiff::encoder::{colortype::Gray32Float, TiffEncoder};
+
+const NPY_MAGIC: &[u8] = b"\x93NUMPY\x01\x00";
+const NPY_ALIGNMENT: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    Png,
+    Exr,
+    Tiff,
+    Npy,
+    NpyStack,
+}
+
+impl Default for OutputFormat {
+    fn default() -> Self {
+        return OutputFormat::Png;
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OutputFormat::Png => write!(f, "png"),
+            OutputFormat::Exr => write!(f, "exr"),
+            OutputFormat::Tiff => write!(f, "tiff"),
+            OutputFormat::Npy => write!(f, "npy"),
+            OutputFormat::NpyStack => write!(f, "npy_stack"),
+        }
+    }
+}
+
+pub struct FrameOutput {
+    directory: String,
+    format: OutputFormat,
+    png_options: PngOptions,
+    res_x: usize,
+    res_y: usize,
+    npy_stack: Option<NpyStack>,
+}
+
+impl FrameOutput {
+    pub fn new(
+        directory: &str,
+        format: OutputFormat,
+        png_options: PngOptions,
+        frames_number: usize,
+        res_x: usize,
+        res_y: usize,
+    ) -> Result<FrameOutput, Box<dyn Error>> {
+        let npy_stack = match format {
+            OutputFormat::NpyStack => {
+                let path = format!("{}/frames.npy", directory);
+
+                match NpyStack::create(&path, frames_number, res_x, res_y) {
+                    Ok(npy_stack) => Some(npy_stack),
+                    Err(error) => {
+                        return Err(classified(
+                            ErrorKind::Io,
+                            format!(
+                                "Frame Output Error: cannot create file \'{}\'! Details: {}",
+                                path, error
+                            ),
+                        ));
+                    }
+                }
+            }
+            _ => None,
+        };
+
+        return Ok(FrameOutput {
+            directory: String::from(directory),
+            format,
+            png_options,
+            res_x,
+            res_y,
+            npy_stack,
+        });
+    }
+
+    pub fn frame_path(&self, frame: usize) -> String {
+        match self.format {
+            OutputFormat::Png => format!("{}/frame_{}.png", self.directory, frame),
+            OutputFormat::Exr => format!("{}/frame_{}.exr", self.directory, frame),
+            OutputFormat::Tiff => format!("{}/frame_{}.tiff", self.directory, frame),
+            OutputFormat::Npy => format!("{}/frame_{}.npy", self.directory, frame),
+            OutputFormat::NpyStack => format!("{}/frames.npy[{}]", self.directory, frame),
+        }
+    }
+
+    /// `tone_curve` only applies to PNG frames; the other formats keep the raw mass.
+    pub fn write(
+        &self,
+        frame: usize,
+        mass_distr: &[f32],
+        tone_curve: ToneCurve,
+    ) -> Result<(), Box<dyn Error>> {
+        let path = self.frame_path(frame);
+
+        match self.format {
+            OutputFormat::Png => save_frame(
+                &path,
+                mass_distr,
+                self.res_x,
+                self.res_y,
+                tone_curve.midpoint,
+                tone_curve.slope,
+                &self.png_options,
+            )
+            .map_err(|error| frame_error(&path, error.to_string())),
+            OutputFormat::Exr => save_frame_exr(&path, mass_distr, self.res_x, self.res_y),
+            OutputFormat::Tiff => save_frame_tiff(&path, mass_distr, self.res_x, self.res_y),
+            OutputFormat::Npy => save_frame_npy(&path, mass_distr, self.res_x, self.res_y),
+            OutputFormat::NpyStack => match &self.npy_stack {
+                Some(npy_stack) => npy_stack.write_frame(frame, mass_distr),
+                None => unreachable!(),
+            },
+        }
+    }
+}
+
+pub fn save_frame_exr(
+    image_file_path: &str,
+    mass_distr: &[f32],
+    res_x: usize,
+    res_y: usize,
+) -> Result<(), Box<dyn Error>> {
+    let channels = SpecificChannels::build()
+        .with_channel("Y")
+        .with_pixel_fn(|position| (mass_distr[position.y() * res_x + position.x()],));
+
+    match Image::from_channels((res_x, res_y), channels)
+        .write()
+        .to_file(image_file_path)
+    {
+        Ok(_) => {
+            return Ok(());
+        }
+        Err(error) => {
+            return Err(frame_error(image_file_path, error.to_string()));
+        }
+    }
+}
+
+pub fn save_frame_tiff(
+    image_file_path: &str,
+    mass_distr: &[f32],
+    res_x: usize,
+    res_y: usize,
+) -> Result<(), Box<dyn Error>> {
+    let file = match File::create(image_file_path) {
+        Ok(file) => file,
+        Err(error) => {
+            return Err(frame_error(image_file_path, error.to_string()));
+        }
+    };
+    let mut encoder = match TiffEncoder::new(BufWriter::new(file)) {
+        Ok(encoder) => encoder,
+        Err(error) => {
+            return Err(frame_error(image_file_path, error.to_string()));
+        }
+    };
+
+    match encoder.write_image::<Gray32Float>(res_x as u32, res_y as u32, mass_distr) {
+        Ok(_) => {
+            return Ok(());
+        }
+        Err(error) => {
+            return Err(frame_error(image_file_path, error.to_string()));
+        }
+    }
+}
+
+pub fn save_frame_npy(
+    image_file_path: &str,
+    mass_distr: &[f32],
+    res_x: usize,
+    res_y: usize,
+) -> Result<(), Box<dyn Error>> {
+    let mut writer = match File::create(image_file_path) {
+        Ok(file) => BufWriter::new(file),
+        Err(error) => {
+            return Err(frame_error(image_file_path, error.to_string()));
+        }
+    };
+
+    match writer
+        .write_all(&npy_header(&format!("({}, {})", res_y, res_x)))
+        .and_then(|_| writer.write_all(&npy_data(mass_distr)))
+        .and_then(|_| writer.flush())
+    {
+        Ok(_) => {
+            return Ok(());
+        }
+        Err(error) => {
+            return Err(frame_error(image_file_path, error.to_string()));
+        }
+    }
+}
+
+pub struct NpyStack {
+    path: String,
+    file: Mutex<File>,
+    header_len: u64,
+    frame_len: u64,
+}
+
+impl NpyStack {
+    pub fn create(
+        path: &str,
+        frames_number: usize,
+        res_x: usize,
+        res_y: usize,
+    ) -> Result<NpyStack, Box<dyn Error>> {
+        let header = npy_header(&format!("({}, {}, {})", frames_number, res_y, res_x));
+        let frame_len = (res_x * res_y * std::mem::size_of::<f32>()) as u64;
+        let mut file = File::create(path)?;
+
+        file.write_all(&header)?;
+        file.set_len(header.len() as u64 + frames_number as u64 * frame_len)?;
+
+        return Ok(NpyStack {
+            path: String::from(path),
+            file: Mutex::new(file),
+            header_len: header.len() as u64,
+            frame_len,
+        });
+    }
+
+    pub fn write_frame(&self, frame: usize, mass_distr: &[f32]) -> Result<(), Box<dyn Error>> {
+        let data = npy_data(mass_distr);
+        let mut file = match self.file.lock() {
+            Ok(file) => file,
+            Err(_) => {
+                return Err("another frame encoder thread has panicked".into());
+            }
+        };
+
+        match file
+            .seek(SeekFrom::Start(
+                self.header_len + frame as u64 * self.frame_len,
+            ))
+            .and_then(|_| file.write_all(&data))
+        {
+            Ok(_) => {
+                return Ok(());
+            }
+            Err(error) => {
+                return Err(frame_error(&self.path, error.to_string()));
+            }
+        }
+    }
+}
+
+fn frame_error(path: &str, details: String) -> Box<dyn Error> {
+    return classified(
+        ErrorKind::Io,
+        format!(
+            "Frame Output Error: cannot save \'{}\'! Details: {}",
+            path, details
+        ),
+    );
+}
+
+fn npy_header(shape: &str) -> Vec<u8> {
+    let mut dictionary = format!(
+        "{{'descr': '<f4', 'fortran_order': False, 'shape': {}, }}",
+        shape
+    );
+    let unpadded_len = NPY_MAGIC.len() + 2 + dictionary.len() + 1;
+    let padding = (NPY_ALIGNMENT - unpadded_len % NPY_ALIGNMENT) % NPY_ALIGNMENT;
+
+    dictionary.push_str(&" ".repeat(padding));
+    dictionary.push('\n');
+
+    let mut header = Vec::with_capacity(NPY_MAGIC.len() + 2 + dictionary.len());
+    header.extend_from_slice(NPY_MAGIC);
+    header.extend_from_slice(&(dictionary.len() as u16).to_le_bytes());
+    header.extend_from_slice(dictionary.as_bytes());
+
+    return header;
+}
+
+fn npy_data(mass_distr: &[f32]) -> Vec<u8> {
+    return mass_distr
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    const RES_X: usize = 5;
+    const RES_Y: usize = 3;
+
+    fn directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!(
+            "fluid_simulation_output_{}_{}",
+            name,
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        return directory;
+    }
+
+    fn mass_distr(frame: usize) -> Vec<f32> {
+        return (0..RES_X * RES_Y)
+            .map(|index| frame as f32 + index as f32 * 0.125 + 1e-7)
+            .collect();
+    }
+
+    fn read_npy(path: &PathBuf) -> (String, Vec<f32>) {
+        let bytes = std::fs::read(path).unwrap();
+        assert_eq!(&bytes[..8], NPY_MAGIC);
+
+        let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+        assert_eq!((10 + header_len) % NPY_ALIGNMENT, 0);
+
+        let header = String::from_utf8(bytes[10..10 + header_len].to_vec()).unwrap();
+        let data = bytes[10 + header_len..]
+            .chunks(4)
+            .map(|value| f32::from_le_bytes([value[0], value[1], value[2], value[3]]))
+            .collect();
+
+        return (header, data);
+    }
+
+    #[test]
+    fn npy_frames_keep_the_exact_values() {
+        let directory = directory("npy");
+        let output = FrameOutput::new(
+            &directory.to_string_lossy(),
+            OutputFormat::Npy,
+            PngOptions::default(),
+            1,
+            RES_X,
+            RES_Y,
+        )
+        .unwrap();
+
+        output
+            .write(0, &mass_distr(0), ToneCurve::default())
+            .unwrap();
+
+        let (header, data) = read_npy(&directory.join("frame_0.npy"));
+        assert!(header.contains("'shape': (3, 5)"));
+        assert!(header.ends_with('\n'));
+        assert_eq!(data, mass_distr(0));
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn npy_stack_places_frames_written_out_of_order() {
+        let directory = directory("npy_stack");
+        let output = FrameOutput::new(
+            &directory.to_string_lossy(),
+            OutputFormat::NpyStack,
+            PngOptions::default(),
+            3,
+            RES_X,
+            RES_Y,
+        )
+        .unwrap();
+
+        for frame in [2, 0, 1] {
+            output
+                .write(frame, &mass_distr(frame), ToneCurve::default())
+                .unwrap();
+        }
+
+        let (header, data) = read_npy(&directory.join("frames.npy"));
+        assert!(header.contains("'shape': (3, 3, 5)"));
+        assert_eq!(data, [mass_distr(0), mass_distr(1), mass_distr(2)].concat());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn raw_frame_errors_name_the_file() {
+        let parent = directory("missing");
+        let path = |extension: &str| {
+            parent
+                .join("missing_directory")
+                .join(format!("frame_0.{}", extension))
+                .to_string_lossy()
+                .into_owned()
+        };
+
+        for (extension, result) in [
+            (
+                "exr",
+                save_frame_exr(&path("exr"), &mass_distr(0), RES_X, RES_Y),
+            ),
+            (
+                "tiff",
+                save_frame_tiff(&path("tiff"), &mass_distr(0), RES_X, RES_Y),
+            ),
+            (
+                "npy",
+                save_frame_npy(&path("npy"), &mass_distr(0), RES_X, RES_Y),
+            ),
+        ] {
+            let error = result.unwrap_err().to_string();
+            assert!(error.contains(&format!("cannot save \'{}\'", path(extension))));
+        }
+
+        std::fs::remove_dir_all(&parent).unwrap();
+    }
+
+    #[test]
+    fn exr_frames_keep_the_exact_values() {
+        let directory = directory("exr");
+        let path = directory.join("frame_0.exr");
+
+        save_frame_exr(&path.to_string_lossy(), &mass_distr(0), RES_X, RES_Y).unwrap();
+
+        let image = exr::prelude::read_first_flat_layer_from_file(&path).unwrap();
+        let channel = &image.layer_data.channel_data.list[0];
+        assert_eq!(image.layer_data.size, exr::math::Vec2(RES_X, RES_Y));
+        assert!(channel.name == *"Y");
+
+        match &channel.sample_data {
+            exr::image::FlatSamples::F32(data) => assert_eq!(data, &mass_distr(0)),
+            _ => panic!("EXR channel is not stored as f32"),
+        }
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn tiff_frames_keep_the_exact_values() {
+        let directory = directory("tiff");
+        let path = directory.join("frame_0.tiff");
+
+        save_frame_tiff(&path.to_string_lossy(), &mass_distr(0), RES_X, RES_Y).unwrap();
+
+        let mut decoder = tiff::decoder::Decoder::new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(decoder.dimensions().unwrap(), (RES_X as u32, RES_Y as u32));
+
+        match decoder.read_image().unwrap() {
+            tiff::decoder::DecodingResult::F32(data) => assert_eq!(data, mass_distr(0)),
+            _ => panic!("TIFF image is not stored as f32"),
+        }
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
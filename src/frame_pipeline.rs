@@ -0,0 +1,291 @@
+use crate::error_kind::{classified, ErrorKind};
+use crate::frame_output::FrameOutput;
+use crate::save_frame::ToneCurve;
+use crate::streamlines::LineFrame;
+
+use rayon::ThreadPool;
+use std::{
+    error::Error,
+    sync::{
+        mpsc::{channel, sync_channel, Receiver, Sender, SyncSender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+enum FrameJob {
+    Mass {
+        frame: usize,
+        mass_distr: Vec<f32>,
+        tone_curve: ToneCurve,
+    },
+    Lines(LineFrame),
+}
+
+pub struct FramePipeline {
+    jobs: Option<SyncSender<FrameJob>>,
+    errors: Receiver<String>,
+    recycled_buffers: Receiver<Vec<f32>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl FramePipeline {
+    pub fn new(
+        workers_number: usize,
+        queue_size: usize,
+        pool: Arc<ThreadPool>,
+        output: FrameOutput,
+    ) -> FramePipeline {
+        let (jobs, job_receiver) = sync_channel::<FrameJob>(queue_size);
+        let (error_sender, errors) = channel::<String>();
+        let (buffer_sender, recycled_buffers) = channel::<Vec<f32>>();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let output = Arc::new(output);
+
+        let workers = (0..workers_number)
+            .map(|index| {
+                let job_receiver = Arc::clone(&job_receiver);
+                let error_sender = error_sender.clone();
+                let buffer_sender = buffer_sender.clone();
+                let pool = Arc::clone(&pool);
+                let output = Arc::clone(&output);
+
+                thread::Builder::new()
+                    .name(format!("frame-encoder-{}", index))
+                    .spawn(move || {
+                        encode_frames(job_receiver, error_sender, buffer_sender, pool, output)
+                    })
+                    .expect("cannot spawn a frame encoder thread")
+            })
+            .collect();
+
+        return FramePipeline {
+            jobs: Some(jobs),
+            errors,
+            recycled_buffers,
+            workers,
+        };
+    }
+
+    pub fn submit(
+        &mut self,
+        frame: usize,
+        mass_distr: &[f32],
+        tone_curve: ToneCurve,
+    ) -> Result<(), Box<dyn Error>> {
+        if let Ok(error) = self.errors.try_recv() {
+            return Err(classified(ErrorKind::Io, error));
+        }
+
+        let mut buffer = match self.recycled_buffers.try_recv() {
+            Ok(buffer) => buffer,
+            Err(_) => Vec::with_capacity(mass_distr.len()),
+        };
+        buffer.clear();
+        buffer.extend_from_slice(mass_distr);
+
+        return self.send(FrameJob::Mass {
+            frame,
+            mass_distr: buffer,
+            tone_curve,
+        });
+    }
+
+    /// Queues a streamline or pathline frame, written by the encoders like the mass frames.
+    pub fn submit_lines(&mut self, line_frame: LineFrame) -> Result<(), Box<dyn Error>> {
+        if let Ok(error) = self.errors.try_recv() {
+            return Err(classified(ErrorKind::Io, error));
+        }
+
+        return self.send(FrameJob::Lines(line_frame));
+    }
+
+    fn send(&mut self, job: FrameJob) -> Result<(), Box<dyn Error>> {
+        match self.jobs.as_ref().map(|jobs| jobs.send(job)) {
+            Some(Ok(_)) => {
+                return Ok(());
+            }
+            _ => match self.errors.try_recv() {
+                Ok(error) => {
+                    return Err(classified(ErrorKind::Io, error));
+                }
+                Err(_) => {
+                    return Err(classified(
+                        ErrorKind::Io,
+                        String::from(
+                            "Frame Encoding Error: all frame encoder threads have stopped!",
+                        ),
+                    ));
+                }
+            },
+        }
+    }
+
+    pub fn finish(mut self) -> Result<(), Box<dyn Error>> {
+        self.join_workers();
+
+        match self.errors.try_recv() {
+            Ok(error) => {
+                return Err(classified(ErrorKind::Io, error));
+            }
+            Err(_) => {
+                return Ok(());
+            }
+        }
+    }
+
+    fn join_workers(&mut self) {
+        self.jobs = None;
+
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl Drop for FramePipeline {
+    fn drop(&mut self) {
+        self.join_workers();
+    }
+}
+
+fn encode_frames(
+    job_receiver: Arc<Mutex<Receiver<FrameJob>>>,
+    error_sender: Sender<String>,
+    buffer_sender: Sender<Vec<f32>>,
+    pool: Arc<ThreadPool>,
+    output: Arc<FrameOutput>,
+) {
+    loop {
+        let job = match job_receiver.lock() {
+            Ok(receiver) => match receiver.recv() {
+                Ok(job) => job,
+                Err(_) => {
+                    return;
+                }
+            },
+            Err(_) => {
+                return;
+            }
+        };
+
+        match job {
+            FrameJob::Mass {
+                frame,
+                mass_distr,
+                tone_curve,
+            } => {
+                let result = pool.install(|| {
+                    output
+                        .write(frame, &mass_distr, tone_curve)
+                        .map_err(|error| error.to_string())
+                });
+
+                // The error already names the frame file.
+                if let Err(error) = result {
+                    let _ = error_sender.send(error);
+                    return;
+                }
+
+                let _ = buffer_sender.send(mass_distr);
+            }
+            FrameJob::Lines(line_frame) => {
+                let result = pool.install(|| line_frame.save().map_err(|error| error.to_string()));
+
+                if let Err(error) = result {
+                    let _ = error_sender.send(error.to_string());
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::error_kind::exit_code;
+    use crate::frame_output::OutputFormat;
+    use crate::save_frame::PngOptions;
+    use crate::streamlines::{LineFormat, LineStyle};
+    use crate::utility::Vec2D;
+
+    use rayon::ThreadPoolBuilder;
+
+    fn pool() -> Arc<ThreadPool> {
+        return Arc::new(ThreadPoolBuilder::new().num_threads(1).build().unwrap());
+    }
+
+    fn png_output(directory: &str) -> FrameOutput {
+        return FrameOutput::new(directory, OutputFormat::Png, PngOptions::default(), 5, 4, 3)
+            .unwrap();
+    }
+
+    #[test]
+    fn frames_are_written_by_background_workers() {
+        let directory =
+            std::env::temp_dir().join(format!("fluid_simulation_pipeline_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let output = png_output(&directory.to_string_lossy());
+        let mut pipeline = FramePipeline::new(2, 1, pool(), output);
+        let mass_distr = vec![0.5; 12];
+
+        for frame in 0..5 {
+            pipeline
+                .submit(frame, &mass_distr, ToneCurve::default())
+                .unwrap();
+            pipeline
+                .submit_lines(LineFrame {
+                    path: directory
+                        .join(format!("streamlines_{}.png", frame))
+                        .to_string_lossy()
+                        .into_owned(),
+                    lines: vec![vec![Vec2D { x: 0.0, y: 1.0 }, Vec2D { x: 3.0, y: 1.0 }]],
+                    format: LineFormat::Png,
+                    res_x: 4,
+                    res_y: 3,
+                    style: LineStyle {
+                        width: 1.0,
+                        color: [255, 255, 255],
+                    },
+                })
+                .unwrap();
+        }
+        pipeline.finish().unwrap();
+
+        for frame in 0..5 {
+            for name in ["frame", "streamlines"] {
+                let path = directory.join(format!("{}_{}.png", name, frame));
+                let frame = image::open(path).unwrap();
+                assert_eq!((frame.width(), frame.height()), (4, 3));
+            }
+        }
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn encoding_errors_are_propagated() {
+        let mut pipeline = FramePipeline::new(1, 1, pool(), png_output("missing_directory"));
+        let mass_distr = vec![0.5; 12];
+        let mut result = Ok(());
+
+        for frame in 0..5 {
+            result = pipeline.submit(frame, &mass_distr, ToneCurve::default());
+            if result.is_err() {
+                break;
+            }
+        }
+
+        let error = match result {
+            Ok(_) => pipeline.finish().unwrap_err(),
+            Err(error) => error,
+        };
+        assert!(error
+            .to_string()
+            .contains("cannot save 'missing_directory/frame_0.png'"));
+        assert_eq!(exit_code(error.as_ref()), ErrorKind::Io.exit_code());
+    }
+}
@@ -0,0 +1,26 @@
+#![allow(clippy::bool_comparison, clippy::needless_return)]
+
+pub mod animation;
+pub mod buoyancy;
+pub mod cli;
+pub mod config_file;
+pub mod contact_sheet;
+pub mod decay;
+pub mod dynamic_flow;
+pub mod error_kind;
+pub mod flow_field;
+pub mod frame_output;
+pub mod frame_pipeline;
+pub mod mass_distr;
+pub mod navier_stokes;
+pub mod output_directory;
+pub mod particles;
+pub mod progress;
+pub mod reaction_diffusion;
+pub mod save_frame;
+pub mod sim_ctrl;
+pub mod simulate;
+pub mod streamlines;
+pub mod sweep;
+pub mod utility;
+pub mod vorticity;
@@ -1,14 +1,15 @@
-mod flow_field;
-mod mass_distr;
-mod save_frame;
-mod sim_ctrl;
-mod simulate;
-mod utility;
+use fluid_simulation::cli::{parse_arguments, Subcommand};
+use fluid_simulation::config_file::write_example_configuration;
+use fluid_simulation::contact_sheet::contact_sheet;
+use fluid_simulation::error_kind::exit_code;
+use fluid_simulation::progress::ProgressMode;
+use fluid_simulation::sim_ctrl::{info, preview, run};
+use fluid_simulation::sweep::{load_sweep_configuration, run_sweep};
 
 use console::style;
-use sim_ctrl::run;
+use std::process::ExitCode;
 
-fn main() {
+fn main() -> ExitCode {
     let wojak = r#"
       ⠘⡀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⡜⠀⠀⠀
     ⠀⠀⠀⠑⡀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⡔⠁⠀⠀⠀
@@ -47,29 +48,66 @@ fn main() {
     ⠀⠀⠀⠈⠻⣿⣦⣈⣧⡀⠀⠀⢸⣿⣿⠀⠀⢀⣼⡀⣨⣿⡿⠁⠀⠀⠀⠀⠀⠀
     ⠀⠀⠀⠀⠀⠈⠻⠿⠿⠓⠄⠤⠘⠉⠙⠤⢀⠾⠿⣿⠟⠋"#;
 
-    let args: Vec<String> = std::env::args().collect();
-    let config_file_path = match args.get(1) {
-        Some(path) => path,
-        None => {
-            eprintln!(
-                "{}\n\n{}\n",
-                style("Provide path to the configuration file...")
-                    .bold()
-                    .yellow(),
-                wojak
-            );
-            return;
+    let invocation = match parse_arguments(std::env::args_os()) {
+        Ok(invocation) => invocation,
+        Err(error) => {
+            error.exit();
         }
     };
 
-    match run(config_file_path) {
-        Ok(_) => {
-            let message = style("Simulation complete!").bold().green();
+    let configuration = invocation.configuration();
+    // JSON progress leaves stdout to the progress lines and stderr to a plain error message.
+    let json_progress = invocation.overrides.progress == Some(ProgressMode::Json)
+        || matches!(&configuration, Ok(Some(config)) if config.progress == ProgressMode::Json);
+
+    let result = configuration.and_then(|config| match (invocation.subcommand, config) {
+        (Subcommand::Run, Some(config)) => run(config).map(|_| Some("Simulation complete!")),
+        (Subcommand::Validate, Some(config)) => {
+            config.check().map(|_| Some("Configuration is valid!"))
+        }
+        (Subcommand::Preview, Some(config)) => preview(config).map(|_| Some("Preview complete!")),
+        (Subcommand::Sweep, _) => {
+            load_sweep_configuration(invocation.config_file_path.as_deref().unwrap_or(""))
+                .and_then(|sweep| run_sweep(sweep, &invocation.overrides))
+                .map(|_| Some("Sweep complete!"))
+        }
+        (Subcommand::ContactSheet, _) => contact_sheet(
+            invocation.directory_path.as_deref().unwrap_or(""),
+            &invocation.contact_sheet,
+        )
+        .map(|_| Some("Contact sheet written!")),
+        (Subcommand::Info, config) => {
+            info(config.as_ref());
+            Ok(None)
+        }
+        (Subcommand::Init, _) => write_example_configuration(
+            invocation
+                .config_file_path
+                .as_deref()
+                .unwrap_or("config.json"),
+            invocation.force,
+        )
+        .map(|_| Some("Example configuration written!")),
+        (_, None) => unreachable!(),
+    });
+
+    match result {
+        // 'info' prints its own output.
+        Ok(None) => ExitCode::SUCCESS,
+        Ok(Some(_)) if json_progress => ExitCode::SUCCESS,
+        Ok(Some(message)) => {
+            let message = style(message).bold().green();
             println!("{}\n\n{}\n", message, pikachu);
+            ExitCode::SUCCESS
+        }
+        Err(error) if json_progress => {
+            eprintln!("{}", error);
+            ExitCode::from(exit_code(error.as_ref()))
         }
         Err(error) => {
             let message = style(format!("{}", error)).bold().red();
             eprintln!("{}\n\n{}\n", message, wojak);
+            ExitCode::from(exit_code(error.as_ref()))
         }
     }
 }
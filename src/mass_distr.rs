@@ -1,6 +1,9 @@
+use crate::error_kind::{classified, ErrorKind};
+
 use image::{GenericImageView, Pixel};
 use std::{error::Error, path::Path};
 
+#[allow(clippy::useless_format)]
 pub fn load_mass_distribution(
     mass_distr_file_path: &str,
     target_res_x: usize,
@@ -23,11 +26,13 @@ pub fn load_mass_distribution(
 
                 return Ok(mass_distribution);
             } else {
-                return Err(format!(
-                    "Error: load_mass_distribution -> \
+                return Err(classified(
+                    ErrorKind::Input,
+                    format!(
+                        "Error: load_mass_distribution -> \
                 indicated file with mass distribution has higher resolution than the target one!"
-                )
-                .into());
+                    ),
+                ));
             }
         }
         Err(error) => {
@@ -35,3 +40,77 @@ pub fn load_mass_distribution(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use image::{GrayImage, Luma};
+    use std::path::PathBuf;
+
+    fn write_image(name: &str, res_x: u32, res_y: u32, value: u8) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "fluid_simulation_{}_{}.png",
+            std::process::id(),
+            name
+        ));
+        GrayImage::from_pixel(res_x, res_y, Luma([value]))
+            .save(&path)
+            .unwrap();
+
+        return path;
+    }
+
+    #[test]
+    fn smaller_image_is_centred_with_zero_padding() {
+        let path = write_image("padding", 4, 2, 255);
+        let mass_distr = load_mass_distribution(path.to_str().unwrap(), 8, 6).unwrap();
+
+        for y in 0..6 {
+            for x in 0..8 {
+                let expected = if (2..6).contains(&x) && (2..4).contains(&y) {
+                    1.0
+                } else {
+                    0.0
+                };
+
+                assert_eq!(mass_distr[y * 8 + x], expected, "cell ({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn odd_padding_is_rounded_down() {
+        let path = write_image("odd_padding", 3, 3, 255);
+        let mass_distr = load_mass_distribution(path.to_str().unwrap(), 6, 4).unwrap();
+
+        let at = |x: usize, y: usize| mass_distr[y * 6 + x];
+
+        assert_eq!(at(0, 0), 0.0);
+        assert_eq!(at(1, 0), 1.0);
+        assert_eq!(at(3, 2), 1.0);
+        assert_eq!(at(4, 0), 0.0);
+        assert_eq!(at(1, 3), 0.0);
+    }
+
+    #[test]
+    fn luma_is_scaled_to_unit_range() {
+        let path = write_image("scaling", 2, 2, 51);
+        let mass_distr = load_mass_distribution(path.to_str().unwrap(), 2, 2).unwrap();
+
+        assert!(mass_distr.iter().all(|value| *value == 0.2));
+    }
+
+    #[test]
+    fn larger_image_is_rejected() {
+        let path = write_image("too_large", 10, 4, 255);
+
+        assert!(load_mass_distribution(path.to_str().unwrap(), 8, 8).is_err());
+        assert!(load_mass_distribution(path.to_str().unwrap(), 12, 3).is_err());
+    }
+
+    #[test]
+    fn missing_file_is_rejected() {
+        assert!(load_mass_distribution("mass_distr/missing.bmp", 8, 8).is_err());
+    }
+}
@@ -0,0 +1,484 @@
+use crate::utility::{bilinear_sample, FlowField};
+use crate::vorticity::{limit_speed, VorticityConfinement};
+
+use rayon::prelude::*;
+use serde::Deserialize;
+use std::fmt;
+
+/// Cells travelled per step at unit speed; the same factor as the mass advection in `simulate`.
+const ADVECTION_FACTOR: f32 = 0.5;
+const DIFFUSION_ITERATIONS: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VelocityMode {
+    CurlNoise,
+    NavierStokes,
+}
+
+impl Default for VelocityMode {
+    fn default() -> Self {
+        return VelocityMode::CurlNoise;
+    }
+}
+
+impl fmt::Display for VelocityMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VelocityMode::CurlNoise => write!(f, "curl_noise"),
+            VelocityMode::NavierStokes => write!(f, "navier_stokes"),
+        }
+    }
+}
+
+/// Stable fluids (J. Stam, 1999) on the simulation grid: semi-Lagrangian self-advection,
+/// implicit viscosity and a Jacobi pressure projection. The border and the obstacle cells
+/// are solid walls; the speed is limited to 1, like the normalised curl-noise field.
+pub struct NavierStokesSolver {
+    res_x: usize,
+    res_y: usize,
+    viscosity: f32,
+    pressure_iterations: usize,
+    vorticity_confinement: Option<VorticityConfinement>,
+    solid: Vec<bool>,
+    velocity: FlowField,
+    velocity_buffer: FlowField,
+    pressure: Vec<f32>,
+    pressure_buffer: Vec<f32>,
+    divergence: Vec<f32>,
+}
+
+impl NavierStokesSolver {
+    pub fn new(
+        res_x: usize,
+        res_y: usize,
+        viscosity: f32,
+        pressure_iterations: usize,
+        vorticity_confinement: f32,
+        obstacles: Option<&Vec<bool>>,
+    ) -> NavierStokesSolver {
+        let solid = (0..res_x * res_y)
+            .map(|index| {
+                let (x, y) = (index % res_x, index / res_x);
+
+                x == 0
+                    || y == 0
+                    || x == res_x - 1
+                    || y == res_y - 1
+                    || obstacles.is_some_and(|obstacles| obstacles[index])
+            })
+            .collect();
+
+        return NavierStokesSolver {
+            res_x,
+            res_y,
+            viscosity,
+            pressure_iterations,
+            vorticity_confinement: if vorticity_confinement > 0.0 {
+                Some(VorticityConfinement::new(
+                    res_x,
+                    res_y,
+                    vorticity_confinement,
+                ))
+            } else {
+                None
+            },
+            solid,
+            velocity: FlowField::new(res_x * res_y),
+            velocity_buffer: FlowField::new(res_x * res_y),
+            pressure: vec![0.0; res_x * res_y],
+            pressure_buffer: vec![0.0; res_x * res_y],
+            divergence: vec![0.0; res_x * res_y],
+        };
+    }
+
+    pub fn velocity(&self) -> &FlowField {
+        return &self.velocity;
+    }
+
+    /// The velocity, for forces that depend on more than a single field (see `buoyancy`).
+    pub fn velocity_mut(&mut self) -> &mut FlowField {
+        return &mut self.velocity;
+    }
+
+    /// Starts from `flow_field` made divergence-free around the obstacles.
+    pub fn seed(&mut self, flow_field: &FlowField) {
+        self.velocity.x.copy_from_slice(&flow_field.x);
+        self.velocity.y.copy_from_slice(&flow_field.y);
+        self.pressure.fill(0.0);
+
+        self.enforce_walls();
+        self.project();
+        limit_speed(&mut self.velocity);
+    }
+
+    /// Advances the velocity by one simulation step, adding `strength * force` and the
+    /// vorticity confinement first.
+    pub fn step(&mut self, force: Option<(&FlowField, f32)>) {
+        if let Some((force, strength)) = force {
+            for (values, force) in [
+                (&mut self.velocity.x, &force.x),
+                (&mut self.velocity.y, &force.y),
+            ] {
+                values
+                    .par_iter_mut()
+                    .zip(force.par_iter())
+                    .for_each(|(value, force)| *value += strength * force);
+            }
+        }
+
+        if let Some(vorticity_confinement) = &mut self.vorticity_confinement {
+            vorticity_confinement.apply(&mut self.velocity);
+        }
+
+        self.enforce_walls();
+        self.advect();
+        if self.viscosity > 0.0 {
+            self.diffuse();
+        }
+        self.project();
+        limit_speed(&mut self.velocity);
+    }
+
+    pub fn divergence_rms(&self) -> f32 {
+        let squared_sum: f64 = (0..self.res_x * self.res_y)
+            .into_par_iter()
+            .filter(|index| self.solid[*index] == false)
+            .map(|index| (self.divergence_at(index) as f64).powi(2))
+            .sum();
+
+        return (squared_sum / (self.res_x * self.res_y) as f64).sqrt() as f32;
+    }
+
+    fn divergence_at(&self, index: usize) -> f32 {
+        let (u, v, res_x) = (&self.velocity.x, &self.velocity.y, self.res_x);
+
+        return 0.5 * ((u[index + 1] - u[index - 1]) + (v[index + res_x] - v[index - res_x]));
+    }
+
+    fn enforce_walls(&mut self) {
+        let solid = &self.solid;
+
+        self.velocity
+            .x
+            .par_iter_mut()
+            .zip(self.velocity.y.par_iter_mut())
+            .zip(solid.par_iter())
+            .filter(|(_, solid)| **solid)
+            .for_each(|((u, v), _)| {
+                *u = 0.0;
+                *v = 0.0;
+            });
+    }
+
+    fn advect(&mut self) {
+        let (res_x, res_y) = (self.res_x, self.res_y);
+        let (velocity, solid) = (&self.velocity, &self.solid);
+
+        self.velocity_buffer
+            .x
+            .par_chunks_mut(res_x)
+            .zip(self.velocity_buffer.y.par_chunks_mut(res_x))
+            .enumerate()
+            .for_each(|(y, (row_x, row_y))| {
+                for x in 0..res_x {
+                    let index = y * res_x + x;
+
+                    if solid[index] {
+                        row_x[x] = 0.0;
+                        row_y[x] = 0.0;
+                        continue;
+                    }
+
+                    let source_x = (x as f32 - ADVECTION_FACTOR * velocity.x[index])
+                        .clamp(0.0, (res_x - 1) as f32);
+                    let source_y = (y as f32 - ADVECTION_FACTOR * velocity.y[index])
+                        .clamp(0.0, (res_y - 1) as f32);
+
+                    row_x[x] = bilinear_sample(&velocity.x, res_x, res_y, source_x, source_y);
+                    row_y[x] = bilinear_sample(&velocity.y, res_x, res_y, source_x, source_y);
+                }
+            });
+
+        std::mem::swap(&mut self.velocity, &mut self.velocity_buffer);
+    }
+
+    fn diffuse(&mut self) {
+        let (res_x, viscosity) = (self.res_x, self.viscosity);
+        let initial = self.velocity.clone();
+
+        for _ in 0..DIFFUSION_ITERATIONS {
+            for (values, buffer, initial) in [
+                (&self.velocity.x, &mut self.velocity_buffer.x, &initial.x),
+                (&self.velocity.y, &mut self.velocity_buffer.y, &initial.y),
+            ] {
+                let solid = &self.solid;
+
+                buffer
+                    .par_chunks_mut(res_x)
+                    .enumerate()
+                    .for_each(|(y, row)| {
+                        for (x, value) in row.iter_mut().enumerate() {
+                            let index = y * res_x + x;
+
+                            *value = if solid[index] {
+                                0.0
+                            } else {
+                                (initial[index]
+                                    + viscosity
+                                        * (values[index - 1]
+                                            + values[index + 1]
+                                            + values[index - res_x]
+                                            + values[index + res_x]))
+                                    / (1.0 + 4.0 * viscosity)
+                            };
+                        }
+                    });
+            }
+
+            std::mem::swap(&mut self.velocity, &mut self.velocity_buffer);
+        }
+    }
+
+    /// Solves div(grad p) = div(u) with the same central differences that measure the
+    /// divergence, so the projected field is divergence-free in exactly that sense.
+    /// Walls reflect the pressure (zero normal gradient) and have no velocity.
+    fn project(&mut self) {
+        let divergence: Vec<f32> = (0..self.res_x * self.res_y)
+            .into_par_iter()
+            .map(|index| {
+                if self.solid[index] {
+                    0.0
+                } else {
+                    self.divergence_at(index)
+                }
+            })
+            .collect();
+        self.divergence = divergence;
+
+        for _ in 0..self.pressure_iterations {
+            self.pressure_gradient();
+
+            let (res_x, solid) = (self.res_x, &self.solid);
+            let (gradient, pressure, divergence) =
+                (&self.velocity_buffer, &self.pressure, &self.divergence);
+
+            self.pressure_buffer
+                .par_chunks_mut(res_x)
+                .enumerate()
+                .for_each(|(y, row)| {
+                    for (x, value) in row.iter_mut().enumerate() {
+                        let index = y * res_x + x;
+
+                        if solid[index] {
+                            *value = 0.0;
+                            continue;
+                        }
+
+                        let laplacian = 0.5
+                            * ((gradient.x[index + 1] - gradient.x[index - 1])
+                                + (gradient.y[index + res_x] - gradient.y[index - res_x]));
+                        let fluid_neighbours = [index - 1, index + 1, index - res_x, index + res_x]
+                            .iter()
+                            .filter(|neighbour| solid[**neighbour] == false)
+                            .count();
+
+                        // Jacobi update; the diagonal of the operator is -fluid_neighbours / 4.
+                        *value = if fluid_neighbours > 0 {
+                            pressure[index]
+                                - 4.0 * (divergence[index] - laplacian) / fluid_neighbours as f32
+                        } else {
+                            0.0
+                        };
+                    }
+                });
+
+            std::mem::swap(&mut self.pressure, &mut self.pressure_buffer);
+        }
+
+        self.pressure_gradient();
+
+        for (values, gradient) in [
+            (&mut self.velocity.x, &self.velocity_buffer.x),
+            (&mut self.velocity.y, &self.velocity_buffer.y),
+        ] {
+            values
+                .par_iter_mut()
+                .zip(gradient.par_iter())
+                .for_each(|(value, gradient)| *value -= gradient);
+        }
+    }
+
+    /// Central-difference pressure gradient in `velocity_buffer`, zero in the walls.
+    fn pressure_gradient(&mut self) {
+        let (res_x, pressure, solid) = (self.res_x, &self.pressure, &self.solid);
+        let pressure_at = |index: usize, neighbour: usize| {
+            if solid[neighbour] {
+                pressure[index]
+            } else {
+                pressure[neighbour]
+            }
+        };
+
+        self.velocity_buffer
+            .x
+            .par_chunks_mut(res_x)
+            .zip(self.velocity_buffer.y.par_chunks_mut(res_x))
+            .enumerate()
+            .for_each(|(y, (row_x, row_y))| {
+                for x in 0..res_x {
+                    let index = y * res_x + x;
+
+                    if solid[index] {
+                        row_x[x] = 0.0;
+                        row_y[x] = 0.0;
+                        continue;
+                    }
+
+                    row_x[x] =
+                        0.5 * (pressure_at(index, index + 1) - pressure_at(index, index - 1));
+                    row_y[x] = 0.5
+                        * (pressure_at(index, index + res_x) - pressure_at(index, index - res_x));
+                }
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::flow_field::generate_flow_field;
+
+    const RES_X: usize = 64;
+    const RES_Y: usize = 48;
+
+    fn curl_noise() -> FlowField {
+        let mut flow_field = FlowField::new(RES_X * RES_Y);
+        generate_flow_field(
+            &mut flow_field,
+            &mut Vec::new(),
+            RES_X,
+            RES_Y,
+            12.0,
+            0.0,
+            0.0,
+            0.0,
+        );
+
+        return flow_field;
+    }
+
+    fn obstacle() -> Vec<bool> {
+        return (0..RES_X * RES_Y)
+            .map(|index| {
+                let (x, y) = ((index % RES_X) as f32 - 32.0, (index / RES_X) as f32 - 24.0);
+
+                x * x + y * y < 36.0
+            })
+            .collect();
+    }
+
+    fn kinetic_energy(flow_field: &FlowField) -> f32 {
+        return flow_field.iter().map(|value| value.length().powi(2)).sum();
+    }
+
+    #[test]
+    fn projection_removes_divergence() {
+        let mut flow_field = FlowField::new(RES_X * RES_Y);
+        for index in 0..RES_X * RES_Y {
+            let (x, y) = ((index % RES_X) as f32, (index / RES_X) as f32);
+            flow_field.x[index] = (x * 0.3).sin() * (y * 0.2).cos() * 0.5;
+            flow_field.y[index] = (x * 0.1).cos() * 0.5;
+        }
+
+        let mut unprojected = NavierStokesSolver::new(RES_X, RES_Y, 0.0, 0, 0.0, None);
+        unprojected.seed(&flow_field);
+        let mut solver = NavierStokesSolver::new(RES_X, RES_Y, 0.0, 200, 0.0, None);
+        solver.seed(&flow_field);
+
+        assert!(solver.divergence_rms() < 0.1 * unprojected.divergence_rms());
+    }
+
+    #[test]
+    fn walls_and_obstacles_stay_at_rest() {
+        let obstacle = obstacle();
+        let mut solver = NavierStokesSolver::new(RES_X, RES_Y, 0.01, 40, 0.0, Some(&obstacle));
+        let force = curl_noise();
+
+        solver.seed(&force);
+        for _ in 0..10 {
+            solver.step(Some((&force, 0.1)));
+        }
+
+        for (index, value) in solver.velocity().iter().enumerate() {
+            let (x, y) = (index % RES_X, index / RES_X);
+
+            if obstacle[index] || x == 0 || y == 0 || x == RES_X - 1 || y == RES_Y - 1 {
+                assert_eq!((value.x, value.y), (0.0, 0.0));
+            }
+            assert!(value.length() <= 1.0 + 1e-6);
+        }
+        assert!(kinetic_energy(solver.velocity()) > 0.0);
+    }
+
+    #[test]
+    fn viscosity_dissipates_energy() {
+        let mut inviscid = NavierStokesSolver::new(RES_X, RES_Y, 0.0, 40, 0.0, None);
+        let mut viscous = NavierStokesSolver::new(RES_X, RES_Y, 0.5, 40, 0.0, None);
+
+        inviscid.seed(&curl_noise());
+        viscous.seed(&curl_noise());
+        let initial_energy = kinetic_energy(inviscid.velocity());
+
+        for _ in 0..10 {
+            inviscid.step(None);
+            viscous.step(None);
+        }
+
+        assert!(kinetic_energy(inviscid.velocity()) < initial_energy);
+        assert!(kinetic_energy(viscous.velocity()) < 0.8 * kinetic_energy(inviscid.velocity()));
+    }
+
+    #[test]
+    fn fluid_keeps_moving_without_forcing() {
+        let mut solver = NavierStokesSolver::new(RES_X, RES_Y, 0.0, 40, 0.0, None);
+        solver.seed(&curl_noise());
+        let initial = solver.velocity().clone();
+
+        for _ in 0..5 {
+            solver.step(None);
+        }
+
+        let changed = solver
+            .velocity()
+            .iter()
+            .zip(initial.iter())
+            .any(|(a, b)| (a.x - b.x).abs() > 1e-3 || (a.y - b.y).abs() > 1e-3);
+
+        assert!(changed);
+        assert!(kinetic_energy(solver.velocity()) > 0.5 * kinetic_energy(&initial));
+    }
+
+    #[test]
+    fn vorticity_confinement_keeps_swirls() {
+        let mut plain = NavierStokesSolver::new(RES_X, RES_Y, 0.0, 40, 0.0, None);
+        let mut confined = NavierStokesSolver::new(RES_X, RES_Y, 0.0, 40, 0.3, None);
+
+        plain.seed(&curl_noise());
+        confined.seed(&curl_noise());
+        for _ in 0..20 {
+            plain.step(None);
+            confined.step(None);
+        }
+
+        let enstrophy = |solver: &NavierStokesSolver| -> f32 {
+            let mut curl = VorticityConfinement::new(RES_X, RES_Y, 0.0);
+            curl.apply(&mut solver.velocity().clone());
+
+            curl.vorticity().iter().map(|value| value * value).sum()
+        };
+
+        assert!(enstrophy(&confined) > enstrophy(&plain));
+    }
+}
@@ -0,0 +1,236 @@
+use crate::error_kind::{classified, ErrorKind};
+
+use serde::Deserialize;
+use std::{
+    error::Error,
+    fmt, fs,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputPolicy {
+    Overwrite,
+    Refuse,
+    Timestamped,
+}
+
+impl Default for OutputPolicy {
+    fn default() -> Self {
+        return OutputPolicy::Refuse;
+    }
+}
+
+impl fmt::Display for OutputPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OutputPolicy::Overwrite => write!(f, "overwrite"),
+            OutputPolicy::Refuse => write!(f, "refuse"),
+            OutputPolicy::Timestamped => write!(f, "timestamped"),
+        }
+    }
+}
+
+/// Names of the files in `directory` that look like frames (or particle trajectories
+/// and line renderings) of an earlier run.
+pub fn existing_frames(directory: &str) -> Vec<String> {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(_) => {
+            return Vec::new();
+        }
+    };
+
+    let mut frames: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|name| {
+            name.starts_with("frame_")
+                || name.starts_with("streamlines_")
+                || name.starts_with("pathlines_")
+                || name == "frames.npy"
+                || name == "particles.csv"
+        })
+        .collect();
+    frames.sort();
+
+    return frames;
+}
+
+/// The closest directory on the way up from `directory` that already exists.
+pub fn existing_ancestor(directory: &str) -> Option<&Path> {
+    return Path::new(directory)
+        .ancestors()
+        .map(|ancestor| {
+            if ancestor.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                ancestor
+            }
+        })
+        .find(|ancestor| ancestor.exists());
+}
+
+/// Creates the output directory and returns the directory the frames should be written to.
+pub fn prepare_output_directory(
+    directory: &str,
+    policy: OutputPolicy,
+) -> Result<String, Box<dyn Error>> {
+    let mut output_directory_path = String::from(directory);
+
+    if policy == OutputPolicy::Timestamped {
+        let base = format!("{}/run_{}", directory, timestamp(SystemTime::now()));
+        let mut suffix = 1;
+
+        output_directory_path = base.clone();
+        while Path::new(&output_directory_path).exists() {
+            suffix += 1;
+            output_directory_path = format!("{}_{}", base, suffix);
+        }
+    }
+
+    match fs::create_dir_all(&output_directory_path) {
+        Ok(_) => {
+            return Ok(output_directory_path);
+        }
+        Err(error) => {
+            return Err(classified(
+                ErrorKind::Io,
+                format!(
+                    "Output Directory Error: cannot create directory \'{}\'! Details: {}",
+                    output_directory_path, error
+                ),
+            ));
+        }
+    }
+}
+
+/// UTC time as 'YYYYMMDD_HHMMSS'.
+pub fn timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    let (days, seconds_of_day) = (seconds / 86400, seconds % 86400);
+
+    // Civil date from the number of days since 1970-01-01 (H. Hinnant's algorithm).
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    return format!(
+        "{:04}{:02}{:02}_{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day % 3600 / 60,
+        seconds_of_day % 60
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    fn directory(name: &str) -> String {
+        let directory = std::env::temp_dir().join(format!(
+            "fluid_simulation_output_directory_{}_{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&directory);
+
+        return directory.to_string_lossy().into_owned();
+    }
+
+    #[test]
+    fn timestamps_are_utc_calendar_dates() {
+        assert_eq!(timestamp(UNIX_EPOCH), "19700101_000000");
+        assert_eq!(
+            timestamp(UNIX_EPOCH + Duration::from_secs(951_782_400 + 3_723)),
+            "20000229_010203"
+        );
+        assert_eq!(
+            timestamp(UNIX_EPOCH + Duration::from_secs(1_792_367_999)),
+            "20261018_235959"
+        );
+    }
+
+    #[test]
+    fn missing_directories_are_created() {
+        let base = directory("create");
+        let nested = format!("{}/a/b", base);
+
+        assert_eq!(
+            existing_ancestor(&nested),
+            Some(std::env::temp_dir().as_path())
+        );
+        assert_eq!(
+            prepare_output_directory(&nested, OutputPolicy::Refuse).unwrap(),
+            nested
+        );
+        assert!(Path::new(&nested).is_dir());
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn timestamped_runs_get_fresh_subdirectories() {
+        let base = directory("timestamped");
+
+        let first = prepare_output_directory(&base, OutputPolicy::Timestamped).unwrap();
+        let second = prepare_output_directory(&base, OutputPolicy::Timestamped).unwrap();
+
+        assert!(first.starts_with(&format!("{}/run_", base)));
+        assert_ne!(first, second);
+        assert!(Path::new(&first).is_dir() && Path::new(&second).is_dir());
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn earlier_frames_are_found() {
+        let base = directory("frames");
+        fs::create_dir_all(&base).unwrap();
+
+        assert!(existing_frames(&base).is_empty());
+
+        for name in [
+            "frame_1.png",
+            "frame_0.png",
+            "mass.png",
+            "frames.npy",
+            "particles.csv",
+            "streamlines_0.svg",
+        ] {
+            fs::write(Path::new(&base).join(name), b"").unwrap();
+        }
+        assert_eq!(
+            existing_frames(&base),
+            vec![
+                "frame_0.png",
+                "frame_1.png",
+                "frames.npy",
+                "particles.csv",
+                "streamlines_0.svg"
+            ]
+        );
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
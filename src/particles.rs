@@ -0,0 +1,523 @@
+use crate::error_kind::{classified, ErrorKind};
+use crate::utility::{FlowField, Vec2D};
+
+use rand::{distributions::Uniform, rngs::StdRng, Rng};
+use rayon::prelude::*;
+use serde::Deserialize;
+use std::{
+    error::Error,
+    fmt,
+    fs::File,
+    io::{BufWriter, Write},
+};
+
+/// Cells travelled per step at unit speed; the same factor as the mass advection in `simulate`.
+const TIME_STEP: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParticleSeeding {
+    Random,
+    Grid,
+    Mass,
+}
+
+impl Default for ParticleSeeding {
+    fn default() -> Self {
+        return ParticleSeeding::Random;
+    }
+}
+
+impl fmt::Display for ParticleSeeding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParticleSeeding::Random => write!(f, "random"),
+            ParticleSeeding::Grid => write!(f, "grid"),
+            ParticleSeeding::Mass => write!(f, "mass"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParticleIntegrator {
+    Rk2,
+    Rk4,
+}
+
+impl Default for ParticleIntegrator {
+    fn default() -> Self {
+        return ParticleIntegrator::Rk2;
+    }
+}
+
+impl fmt::Display for ParticleIntegrator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParticleIntegrator::Rk2 => write!(f, "rk2"),
+            ParticleIntegrator::Rk4 => write!(f, "rk4"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParticleRendering {
+    None,
+    Dots,
+    Trails,
+}
+
+impl Default for ParticleRendering {
+    fn default() -> Self {
+        return ParticleRendering::Dots;
+    }
+}
+
+impl fmt::Display for ParticleRendering {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParticleRendering::None => write!(f, "none"),
+            ParticleRendering::Dots => write!(f, "dots"),
+            ParticleRendering::Trails => write!(f, "trails"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParticleLayer {
+    Over,
+    Alone,
+}
+
+impl Default for ParticleLayer {
+    fn default() -> Self {
+        return ParticleLayer::Over;
+    }
+}
+
+impl fmt::Display for ParticleLayer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParticleLayer::Over => write!(f, "over"),
+            ParticleLayer::Alone => write!(f, "alone"),
+        }
+    }
+}
+
+/// Initial particle positions (in cells) on a `res_x` x `res_y` grid.
+pub fn seed_particles(
+    seeding: ParticleSeeding,
+    number: usize,
+    mass_distr: &Vec<f32>,
+    res_x: usize,
+    res_y: usize,
+    rng: &mut StdRng,
+) -> Vec<Vec2D> {
+    let (range_x, range_y) = (
+        Uniform::new(1.0, (res_x - 2) as f32),
+        Uniform::new(1.0, (res_y - 2) as f32),
+    );
+    let total_mass: f64 = mass_distr.iter().map(|mass| mass.max(0.0) as f64).sum();
+
+    match seeding {
+        ParticleSeeding::Grid => {
+            let columns = ((number as f32 * res_x as f32 / res_y as f32).sqrt().round() as usize)
+                .clamp(1, number.max(1));
+            let rows = number.div_ceil(columns);
+
+            return (0..number)
+                .map(|particle| Vec2D {
+                    x: ((particle % columns) as f32 + 0.5) * res_x as f32 / columns as f32,
+                    y: ((particle / columns) as f32 + 0.5) * res_y as f32 / rows as f32,
+                })
+                .collect();
+        }
+        ParticleSeeding::Mass if total_mass > 0.0 => {
+            let mut cumulative_mass = Vec::with_capacity(mass_distr.len());
+            let mut sum = 0.0;
+
+            for mass in mass_distr {
+                sum += mass.max(0.0) as f64;
+                cumulative_mass.push(sum);
+            }
+
+            return (0..number)
+                .map(|_| {
+                    let target = rng.gen_range(0.0..total_mass);
+                    let index = cumulative_mass
+                        .partition_point(|sum| *sum <= target)
+                        .min(mass_distr.len() - 1);
+
+                    Vec2D {
+                        x: (index % res_x) as f32 + rng.gen_range(0.0..1.0),
+                        y: (index / res_x) as f32 + rng.gen_range(0.0..1.0),
+                    }
+                })
+                .collect();
+        }
+        // Without any mass the particles are spread uniformly.
+        ParticleSeeding::Random | ParticleSeeding::Mass => {
+            return (0..number)
+                .map(|_| Vec2D {
+                    x: rng.sample(range_x),
+                    y: rng.sample(range_y),
+                })
+                .collect();
+        }
+    }
+}
+
+/// Lagrangian tracers carried by the flow field and drawn on the frames, optionally
+/// recording their trajectories to a CSV file.
+pub struct Particles {
+    res_x: usize,
+    res_y: usize,
+    integrator: ParticleIntegrator,
+    rendering: ParticleRendering,
+    layer: ParticleLayer,
+    trail_fade: f32,
+    positions: Vec<Vec2D>,
+    trails: Vec<f32>,
+    csv: Option<(String, BufWriter<File>)>,
+}
+
+impl Particles {
+    pub fn new(
+        res_x: usize,
+        res_y: usize,
+        positions: Vec<Vec2D>,
+        integrator: ParticleIntegrator,
+        rendering: ParticleRendering,
+        layer: ParticleLayer,
+        trail_fade: f32,
+    ) -> Particles {
+        return Particles {
+            res_x,
+            res_y,
+            integrator,
+            rendering,
+            layer,
+            trail_fade,
+            positions,
+            trails: vec![0.0; res_x * res_y],
+            csv: None,
+        };
+    }
+
+    pub fn positions(&self) -> &Vec<Vec2D> {
+        return &self.positions;
+    }
+
+    /// Records the positions of every frame to the CSV file at `path`.
+    pub fn record_trajectories(&mut self, path: &str) -> Result<(), Box<dyn Error>> {
+        let mut writer = match File::create(path) {
+            Ok(file) => BufWriter::new(file),
+            Err(error) => {
+                return Err(csv_error(path, error));
+            }
+        };
+
+        match writeln!(writer, "frame,particle,x,y") {
+            Ok(_) => {}
+            Err(error) => {
+                return Err(csv_error(path, error));
+            }
+        }
+
+        self.csv = Some((String::from(path), writer));
+
+        return Ok(());
+    }
+
+    /// Moves every particle by one simulation step through `flow_field`.
+    pub fn advance(&mut self, flow_field: &FlowField) {
+        let (res_x, res_y, integrator) = (self.res_x, self.res_y, self.integrator);
+        let velocity = |position: &Vec2D| flow_field.sample(res_x, res_y, position.x, position.y);
+        let moved = |position: &Vec2D, velocity: &Vec2D, time: f32| Vec2D {
+            x: position.x + time * velocity.x,
+            y: position.y + time * velocity.y,
+        };
+
+        self.positions.par_iter_mut().for_each(|position| {
+            let k1 = velocity(position);
+            let step = match integrator {
+                ParticleIntegrator::Rk2 => velocity(&moved(position, &k1, 0.5 * TIME_STEP)),
+                ParticleIntegrator::Rk4 => {
+                    let k2 = velocity(&moved(position, &k1, 0.5 * TIME_STEP));
+                    let k3 = velocity(&moved(position, &k2, 0.5 * TIME_STEP));
+                    let k4 = velocity(&moved(position, &k3, TIME_STEP));
+
+                    Vec2D {
+                        x: (k1.x + 2.0 * k2.x + 2.0 * k3.x + k4.x) / 6.0,
+                        y: (k1.y + 2.0 * k2.y + 2.0 * k3.y + k4.y) / 6.0,
+                    }
+                }
+            };
+
+            *position = moved(position, &step, TIME_STEP);
+            position.x = position.x.clamp(0.0, (res_x - 1) as f32);
+            position.y = position.y.clamp(0.0, (res_y - 1) as f32);
+        });
+
+        if self.rendering == ParticleRendering::Trails {
+            self.draw_positions();
+        }
+    }
+
+    /// Composes the frame written to the output from the mass distribution and the particles.
+    pub fn render(&mut self, mass_distr: &Vec<f32>, frame: &mut Vec<f32>) {
+        match self.rendering {
+            ParticleRendering::None => {
+                frame.copy_from_slice(mass_distr);
+                return;
+            }
+            ParticleRendering::Dots => {
+                self.trails.fill(0.0);
+                self.draw_positions();
+            }
+            ParticleRendering::Trails => {}
+        }
+
+        let layer = self.layer;
+
+        frame
+            .par_iter_mut()
+            .zip(mass_distr.par_iter().zip(self.trails.par_iter()))
+            .for_each(|(value, (mass, trail))| {
+                *value = match layer {
+                    ParticleLayer::Over => mass.max(*trail),
+                    ParticleLayer::Alone => *trail,
+                };
+            });
+
+        if self.rendering == ParticleRendering::Trails {
+            let trail_fade = self.trail_fade;
+
+            self.trails
+                .par_iter_mut()
+                .for_each(|trail| *trail *= trail_fade);
+        }
+    }
+
+    /// Appends the current positions, as the positions at `frame`, to the CSV file.
+    pub fn write_trajectories(&mut self, frame: usize) -> Result<(), Box<dyn Error>> {
+        let (path, writer) = match &mut self.csv {
+            Some(csv) => csv,
+            None => {
+                return Ok(());
+            }
+        };
+
+        for (particle, position) in self.positions.iter().enumerate() {
+            match writeln!(
+                writer,
+                "{},{},{},{}",
+                frame, particle, position.x, position.y
+            ) {
+                Ok(_) => {}
+                Err(error) => {
+                    return Err(csv_error(path, error));
+                }
+            }
+        }
+
+        return Ok(());
+    }
+
+    pub fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some((path, writer)) = &mut self.csv {
+            match writer.flush() {
+                Ok(_) => {}
+                Err(error) => {
+                    return Err(csv_error(path, error));
+                }
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn draw_positions(&mut self) {
+        for position in &self.positions {
+            let (x, y) = (position.x.round() as usize, position.y.round() as usize);
+
+            self.trails[y.min(self.res_y - 1) * self.res_x + x.min(self.res_x - 1)] = 1.0;
+        }
+    }
+}
+
+fn csv_error(path: &str, error: std::io::Error) -> Box<dyn Error> {
+    return classified(
+        ErrorKind::Io,
+        format!(
+            "Particle Export Error: cannot write trajectories to \'{}\'! Details: {}",
+            path, error
+        ),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::SeedableRng;
+
+    const RES_X: usize = 64;
+    const RES_Y: usize = 48;
+
+    /// Solid-body rotation around the centre of the grid, one radian per 40 time units.
+    fn rotation() -> FlowField {
+        let mut flow_field = FlowField::new(RES_X * RES_Y);
+
+        for index in 0..RES_X * RES_Y {
+            let (x, y) = ((index % RES_X) as f32 - 32.0, (index / RES_X) as f32 - 24.0);
+
+            flow_field.x[index] = -y / 40.0;
+            flow_field.y[index] = x / 40.0;
+        }
+
+        return flow_field;
+    }
+
+    fn particles(positions: Vec<Vec2D>, integrator: ParticleIntegrator) -> Particles {
+        return Particles::new(
+            RES_X,
+            RES_Y,
+            positions,
+            integrator,
+            ParticleRendering::Dots,
+            ParticleLayer::Over,
+            0.9,
+        );
+    }
+
+    /// Distance from the exact position after `time` units of rotation from (48, 24).
+    fn error(particles: &Particles, time: f32) -> f32 {
+        let position = &particles.positions()[0];
+        let angle = time / 40.0;
+
+        return ((position.x - 32.0 - 16.0 * angle.cos()).powi(2)
+            + (position.y - 24.0 - 16.0 * angle.sin()).powi(2))
+        .sqrt();
+    }
+
+    #[test]
+    fn seeding_modes_place_the_requested_number_of_particles() {
+        let mut mass_distr = vec![0.0; RES_X * RES_Y];
+        mass_distr[10 * RES_X + 20] = 1.0;
+        let mut rng = StdRng::seed_from_u64(1);
+
+        for seeding in [
+            ParticleSeeding::Random,
+            ParticleSeeding::Grid,
+            ParticleSeeding::Mass,
+        ] {
+            let positions = seed_particles(seeding, 30, &mass_distr, RES_X, RES_Y, &mut rng);
+
+            assert_eq!(positions.len(), 30);
+            assert!(positions.iter().all(|position| position.x >= 0.0
+                && position.x < RES_X as f32
+                && position.y >= 0.0
+                && position.y < RES_Y as f32));
+        }
+
+        // Particles seeded from the mass all start in the only cell with mass.
+        let positions = seed_particles(
+            ParticleSeeding::Mass,
+            10,
+            &mass_distr,
+            RES_X,
+            RES_Y,
+            &mut rng,
+        );
+        assert!(positions
+            .iter()
+            .all(|position| position.x.floor() == 20.0 && position.y.floor() == 10.0));
+    }
+
+    #[test]
+    fn integration_follows_a_rotation() {
+        let flow_field = rotation();
+        let start = vec![Vec2D { x: 48.0, y: 24.0 }];
+        let mut rk2 = particles(start.clone(), ParticleIntegrator::Rk2);
+        let mut rk4 = particles(start, ParticleIntegrator::Rk4);
+
+        for _ in 0..200 {
+            rk2.advance(&flow_field);
+            rk4.advance(&flow_field);
+        }
+
+        assert!(error(&rk2, 100.0) < 0.01);
+        assert!(error(&rk4, 100.0) < error(&rk2, 100.0));
+    }
+
+    #[test]
+    fn particles_are_drawn_over_the_density() {
+        let mut dots = particles(vec![Vec2D { x: 10.2, y: 5.7 }], ParticleIntegrator::Rk2);
+        let mass_distr = vec![0.25; RES_X * RES_Y];
+        let mut frame = vec![0.0; RES_X * RES_Y];
+
+        dots.render(&mass_distr, &mut frame);
+
+        assert_eq!(frame[6 * RES_X + 10], 1.0);
+        assert_eq!(
+            frame.iter().filter(|value| **value == 0.25).count(),
+            RES_X * RES_Y - 1
+        );
+    }
+
+    #[test]
+    fn trails_fade_out() {
+        let mut trails = Particles::new(
+            RES_X,
+            RES_Y,
+            vec![Vec2D { x: 40.0, y: 24.0 }],
+            ParticleIntegrator::Rk2,
+            ParticleRendering::Trails,
+            ParticleLayer::Alone,
+            0.5,
+        );
+        let flow_field = rotation();
+        let mass_distr = vec![0.25; RES_X * RES_Y];
+        let mut frame = vec![0.0; RES_X * RES_Y];
+
+        for _ in 0..8 {
+            trails.advance(&flow_field);
+        }
+        trails.render(&mass_distr, &mut frame);
+        let drawn = frame.iter().filter(|value| **value == 1.0).count();
+
+        assert!(drawn > 1);
+        assert!(frame.iter().all(|value| *value == 0.0 || *value == 1.0));
+
+        trails.render(&mass_distr, &mut frame);
+
+        assert_eq!(frame.iter().filter(|value| **value == 0.5).count(), drawn);
+    }
+
+    #[test]
+    fn trajectories_are_exported_as_csv() {
+        let path = std::env::temp_dir().join(format!(
+            "fluid_simulation_particles_{}.csv",
+            std::process::id()
+        ));
+        let mut tracers = particles(
+            vec![Vec2D { x: 1.5, y: 2.0 }, Vec2D { x: 3.0, y: 4.25 }],
+            ParticleIntegrator::Rk2,
+        );
+
+        tracers.record_trajectories(path.to_str().unwrap()).unwrap();
+        tracers.write_trajectories(0).unwrap();
+        tracers.write_trajectories(1).unwrap();
+        tracers.finish().unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "frame,particle,x,y\n0,0,1.5,2\n0,1,3,4.25\n1,0,1.5,2\n1,1,3,4.25\n"
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,183 @@
+use indicatif::{ProgressBar, ProgressStyle};
+use rayon::prelude::*;
+use serde::Deserialize;
+use serde_json::json;
+use std::{fmt, time::Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProgressMode {
+    Bar,
+    Json,
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MassStatistics {
+    pub total: f64,
+    pub mean: f64,
+    pub min: f32,
+    pub max: f32,
+}
+
+/// Progress of a run: the bar, or one JSON line per frame on stdout.
+pub struct Progress {
+    mode: ProgressMode,
+    frames: usize,
+    run: Option<String>,
+    start: Instant,
+    bar: Option<ProgressBar>,
+}
+
+impl Default for ProgressMode {
+    fn default() -> Self {
+        return ProgressMode::Bar;
+    }
+}
+
+impl fmt::Display for ProgressMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProgressMode::Bar => write!(f, "bar"),
+            ProgressMode::Json => write!(f, "json"),
+            ProgressMode::None => write!(f, "none"),
+        }
+    }
+}
+
+impl MassStatistics {
+    pub fn new(mass_distr: &[f32]) -> MassStatistics {
+        let (total, min, max) = mass_distr
+            .par_iter()
+            .map(|value| (*value as f64, *value, *value))
+            .reduce(
+                || (0.0, f32::INFINITY, f32::NEG_INFINITY),
+                |a, b| (a.0 + b.0, a.1.min(b.1), a.2.max(b.2)),
+            );
+
+        return MassStatistics {
+            total,
+            mean: total / mass_distr.len().max(1) as f64,
+            min,
+            max,
+        };
+    }
+
+    /// Whether every value was finite; NaN and infinities propagate into the total.
+    pub fn is_finite(&self) -> bool {
+        return self.total.is_finite();
+    }
+}
+
+impl Progress {
+    /// `run` names the run in the JSON lines, to tell concurrent runs apart.
+    pub fn new(mode: ProgressMode, frames: usize, run: Option<String>) -> Progress {
+        let bar = match mode {
+            ProgressMode::Bar => {
+                let bar = ProgressBar::new(frames as u64);
+                bar.set_style(
+                    ProgressStyle::default_bar()
+                        .template("[{elapsed_precise}] [{bar:70.cyan/blue}] {pos:>7}/{len:7} {msg}")
+                        .progress_chars("#>-"),
+                );
+                Some(bar)
+            }
+            _ => None,
+        };
+
+        return Progress {
+            mode,
+            frames,
+            run,
+            start: Instant::now(),
+            bar,
+        };
+    }
+
+    /// Reports a written frame; `frame` counts from 0.
+    pub fn frame(&self, frame: usize, mass: &MassStatistics) {
+        if let Some(bar) = &self.bar {
+            bar.inc(1);
+        }
+
+        if self.mode == ProgressMode::Json {
+            println!("{}", self.json_line(frame, mass));
+        }
+    }
+
+    pub fn finish(&self) {
+        if let Some(bar) = &self.bar {
+            bar.finish();
+        }
+    }
+
+    fn json_line(&self, frame: usize, mass: &MassStatistics) -> String {
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let done = frame + 1;
+        let eta = elapsed / done as f64 * self.frames.saturating_sub(done) as f64;
+
+        let mut line = json!({
+            "frame": frame,
+            "frames": self.frames,
+            "elapsed": elapsed,
+            "eta": eta,
+            "mass": {
+                "total": mass.total,
+                "mean": mass.mean,
+                "min": mass.min,
+                "max": mass.max,
+            },
+        });
+
+        if let Some(run) = &self.run {
+            line["run"] = json!(run);
+        }
+
+        return line.to_string();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    #[test]
+    fn mass_statistics_cover_the_distribution() {
+        let mass = MassStatistics::new(&[0.0, 0.25, 0.5, 1.25]);
+
+        assert_eq!(mass.total, 2.0);
+        assert_eq!(mass.mean, 0.5);
+        assert_eq!(mass.min, 0.0);
+        assert_eq!(mass.max, 1.25);
+        assert!(mass.is_finite());
+        assert!(MassStatistics::new(&[0.5, f32::NAN]).is_finite() == false);
+    }
+
+    #[test]
+    fn json_lines_report_the_frame_and_the_mass() {
+        let progress = Progress::new(ProgressMode::Json, 10, None);
+        let line: Value =
+            serde_json::from_str(&progress.json_line(4, &MassStatistics::new(&[0.5, 1.5])))
+                .unwrap();
+
+        assert_eq!(line["frame"], 4);
+        assert_eq!(line["frames"], 10);
+        assert!(line["elapsed"].as_f64().unwrap() >= 0.0);
+        assert!(line["eta"].as_f64().unwrap() >= 0.0);
+        assert_eq!(line["mass"]["total"], 2.0);
+        assert_eq!(line["mass"]["mean"], 1.0);
+        assert_eq!(line["mass"]["min"], 0.5);
+        assert_eq!(line["mass"]["max"], 1.5);
+        assert!(line.get("run").is_none());
+    }
+
+    #[test]
+    fn json_lines_name_the_run() {
+        let progress = Progress::new(ProgressMode::Json, 10, Some(String::from("000_seed-1")));
+        let line: Value =
+            serde_json::from_str(&progress.json_line(0, &MassStatistics::new(&[1.0]))).unwrap();
+
+        assert_eq!(line["run"], "000_seed-1");
+    }
+}
@@ -0,0 +1,230 @@
+use crate::simulate::simulate;
+use crate::utility::FlowField;
+
+use rayon::prelude::*;
+
+/// Gray-Scott reaction-diffusion of two species carried by the flow: U is fed into the
+/// fluid, turned into V by the reaction U + 2V -> 3V, and V is removed at the kill rate.
+/// V plays the role of the mass distribution, so it is what the frames show.
+pub struct ReactionDiffusion {
+    res_x: usize,
+    res_y: usize,
+    feed_rate: f32,
+    kill_rate: f32,
+    diffusion_u: f32,
+    diffusion_v: f32,
+    reaction_steps: usize,
+    u: Vec<f32>,
+    u_buffer: Vec<f32>,
+    v_buffer: Vec<f32>,
+}
+
+impl ReactionDiffusion {
+    /// Starts from U = 1 - V / 2, so the mass distribution `v` seeds the reaction.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        res_x: usize,
+        res_y: usize,
+        feed_rate: f32,
+        kill_rate: f32,
+        diffusion_u: f32,
+        diffusion_v: f32,
+        reaction_steps: usize,
+        v: &[f32],
+    ) -> ReactionDiffusion {
+        let u: Vec<f32> = v.iter().map(|v| 1.0 - 0.5 * v.clamp(0.0, 1.0)).collect();
+
+        // `simulate` leaves the border of its buffer untouched, so both copies of U start
+        // with the same border.
+        return ReactionDiffusion {
+            res_x,
+            res_y,
+            feed_rate,
+            kill_rate,
+            diffusion_u,
+            diffusion_v,
+            reaction_steps,
+            u_buffer: u.clone(),
+            u,
+            v_buffer: vec![0.0; res_x * res_y],
+        };
+    }
+
+    pub fn u(&self) -> &Vec<f32> {
+        return &self.u;
+    }
+
+    /// Advects U along `flow_field` (V is the mass distribution and is advected by the
+    /// caller) and then runs `reaction_steps` explicit reaction-diffusion steps covering
+    /// `fraction` of a simulation step.
+    pub fn advance(&mut self, flow_field: &FlowField, v: &mut Vec<f32>, fraction: f32) {
+        simulate(
+            flow_field,
+            &mut self.u,
+            &mut self.u_buffer,
+            self.res_x,
+            self.res_y,
+        );
+
+        for _ in 0..self.reaction_steps {
+            self.react(v, fraction);
+        }
+    }
+
+    fn react(&mut self, v: &mut Vec<f32>, fraction: f32) {
+        let (res_x, res_y) = (self.res_x, self.res_y);
+        let (feed_rate, kill_rate) = (self.feed_rate, self.kill_rate);
+        let (diffusion_u, diffusion_v) = (self.diffusion_u, self.diffusion_v);
+        let (u, v_values) = (&self.u, &v[..]);
+
+        self.u_buffer
+            .par_chunks_mut(res_x)
+            .zip(self.v_buffer.par_chunks_mut(res_x))
+            .enumerate()
+            .for_each(|(y, (row_u, row_v))| {
+                for x in 0..res_x {
+                    let index = y * res_x + x;
+
+                    if x == 0 || y == 0 || x == res_x - 1 || y == res_y - 1 {
+                        row_u[x] = u[index];
+                        row_v[x] = v_values[index];
+                        continue;
+                    }
+
+                    let laplacian = |values: &[f32]| {
+                        values[index - 1]
+                            + values[index + 1]
+                            + values[index - res_x]
+                            + values[index + res_x]
+                            - 4.0 * values[index]
+                    };
+                    let reaction = u[index] * v_values[index] * v_values[index];
+
+                    row_u[x] = u[index]
+                        + fraction
+                            * (diffusion_u * laplacian(u) - reaction
+                                + feed_rate * (1.0 - u[index]));
+                    row_v[x] = v_values[index]
+                        + fraction
+                            * (diffusion_v * laplacian(v_values) + reaction
+                                - (feed_rate + kill_rate) * v_values[index]);
+                }
+            });
+
+        std::mem::swap(&mut self.u, &mut self.u_buffer);
+        std::mem::swap(v, &mut self.v_buffer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RES_X: usize = 64;
+    const RES_Y: usize = 48;
+
+    fn seed() -> Vec<f32> {
+        return (0..RES_X * RES_Y)
+            .map(|index| {
+                let (x, y) = (index % RES_X, index / RES_X);
+
+                if (28..36).contains(&x) && (20..28).contains(&y) {
+                    0.5
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+    }
+
+    fn reaction_diffusion(v: &[f32]) -> ReactionDiffusion {
+        return ReactionDiffusion::new(RES_X, RES_Y, 0.037, 0.06, 0.2, 0.1, 1, v);
+    }
+
+    #[test]
+    fn homogeneous_state_is_stable() {
+        let mut v = vec![0.0; RES_X * RES_Y];
+        let mut reaction_diffusion = reaction_diffusion(&v);
+        let still = FlowField::new(RES_X * RES_Y);
+
+        for _ in 0..100 {
+            reaction_diffusion.advance(&still, &mut v, 1.0);
+        }
+
+        assert!(v.iter().all(|value| *value == 0.0));
+        assert!(reaction_diffusion.u().iter().all(|value| *value == 1.0));
+    }
+
+    #[test]
+    fn pattern_grows_from_the_seed() {
+        let mut v = seed();
+        let mut reaction_diffusion = reaction_diffusion(&v);
+        let still = FlowField::new(RES_X * RES_Y);
+        let initial = v.iter().filter(|value| **value > 0.1).count();
+
+        for _ in 0..1500 {
+            reaction_diffusion.advance(&still, &mut v, 1.0);
+        }
+
+        assert!(v.iter().filter(|value| **value > 0.1).count() > 2 * initial);
+        assert!(v
+            .iter()
+            .all(|value| value.is_finite() && *value >= 0.0 && *value <= 1.0));
+    }
+
+    #[test]
+    fn fraction_scales_the_reaction() {
+        let still = FlowField::new(RES_X * RES_Y);
+        let change = |fraction: f32| {
+            let mut v = seed();
+            let mut reaction_diffusion = reaction_diffusion(&v);
+            reaction_diffusion.advance(&still, &mut v, fraction);
+
+            v.iter()
+                .zip(seed().iter())
+                .map(|(after, before)| after - before)
+                .collect::<Vec<f32>>()
+        };
+        let (full, half) = (change(1.0), change(0.5));
+
+        assert!(full.iter().any(|value| *value != 0.0));
+        assert!(full
+            .iter()
+            .zip(half.iter())
+            .all(|(full, half)| (full * 0.5 - half).abs() < 1e-6));
+    }
+
+    #[test]
+    fn both_species_follow_the_flow() {
+        let mut v = seed();
+        let mut reaction_diffusion =
+            ReactionDiffusion::new(RES_X, RES_Y, 0.0, 0.0, 0.0, 0.0, 1, &v);
+        let mut flow_field = FlowField::new(RES_X * RES_Y);
+        let mut v_buffer = vec![0.0; RES_X * RES_Y];
+        flow_field.x.fill(1.0);
+
+        let centre = |values: &Vec<f32>, weight: &dyn Fn(f32) -> f32| {
+            let (sum, total) =
+                values
+                    .iter()
+                    .enumerate()
+                    .fold((0.0, 0.0), |(sum, total), (index, value)| {
+                        (
+                            sum + (index % RES_X) as f32 * weight(*value),
+                            total + weight(*value),
+                        )
+                    });
+
+            sum / total
+        };
+
+        for _ in 0..8 {
+            simulate(&flow_field, &mut v, &mut v_buffer, RES_X, RES_Y);
+            reaction_diffusion.advance(&flow_field, &mut v, 1.0);
+        }
+
+        // Four cells to the right, as 8 steps at 0.5 cells per step.
+        assert!((centre(&v, &|value| value) - 35.5).abs() < 0.5);
+        assert!((centre(reaction_diffusion.u(), &|value| 1.0 - value) - 35.5).abs() < 0.5);
+    }
+}
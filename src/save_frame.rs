@@ -1,26 +1,168 @@
-use image::{codecs::png::PngEncoder, ColorType, ImageBuffer, ImageEncoder};
-use std::{error::Error, fs::File, io::BufWriter, path::Path};
+use image::{
+    codecs::png::{CompressionType, FilterType, PngEncoder},
+    ColorType, ImageEncoder,
+};
+use rayon::prelude::*;
+use serde::Deserialize;
+use std::{error::Error, fmt, fs::File, io::BufWriter, path::Path};
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PngCompression {
+    Default,
+    Fast,
+    Best,
+    Huffman,
+    Rle,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PngFilter {
+    None,
+    Sub,
+    Up,
+    Avg,
+    Paeth,
+    Adaptive,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PngColor {
+    Gray,
+    Rgb,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PngOptions {
+    pub compression: PngCompression,
+    pub filter: PngFilter,
+    pub bit_depth: usize,
+    pub color: PngColor,
+}
+
+/// Logistic curve mapping the mass to the grey levels of PNG frames.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneCurve {
+    pub midpoint: f32,
+    pub slope: f32,
+}
+
+impl Default for PngCompression {
+    fn default() -> Self {
+        return PngCompression::Default;
+    }
+}
+
+impl Default for PngFilter {
+    fn default() -> Self {
+        return PngFilter::Adaptive;
+    }
+}
+
+impl Default for PngColor {
+    fn default() -> Self {
+        return PngColor::Gray;
+    }
+}
+
+impl Default for PngOptions {
+    fn default() -> Self {
+        return PngOptions {
+            compression: PngCompression::default(),
+            filter: PngFilter::default(),
+            bit_depth: 8,
+            color: PngColor::default(),
+        };
+    }
+}
+
+impl Default for ToneCurve {
+    fn default() -> Self {
+        return ToneCurve {
+            midpoint: 170.0,
+            slope: 0.03,
+        };
+    }
+}
+
+impl fmt::Display for PngOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}-bit {:?}, {:?} compression, {:?} filter",
+            self.bit_depth, self.color, self.compression, self.filter
+        )
+    }
+}
 
 pub fn save_frame(
     image_file_path: &str,
-    buffer: &Vec<f32>,
+    buffer: &[f32],
     res_x: usize,
     res_y: usize,
     midpoint: f32,
     slope: f32,
+    options: &PngOptions,
 ) -> Result<(), Box<dyn Error>> {
-    let frame = ImageBuffer::from_fn(res_x as u32, res_y as u32, |x, y| {
-        let value = buffer[(y * res_x as u32 + x) as usize] * 255.0;
-        let image_value = (255.0 / (1.0 + (-slope * (value - midpoint)).exp())).round() as u8;
+    let (frame, color_type): (Vec<u8>, ColorType) = match (options.bit_depth, options.color) {
+        (16, PngColor::Gray) => (
+            buffer
+                .par_iter()
+                .flat_map_iter(|value| tone_map_16(*value, midpoint, slope).to_ne_bytes())
+                .collect(),
+            ColorType::L16,
+        ),
+        (16, PngColor::Rgb) => (
+            buffer
+                .par_iter()
+                .flat_map_iter(|value| {
+                    let [first, second] = tone_map_16(*value, midpoint, slope).to_ne_bytes();
+                    [first, second, first, second, first, second]
+                })
+                .collect(),
+            ColorType::Rgb16,
+        ),
+        (_, PngColor::Gray) => (
+            buffer
+                .par_iter()
+                .map(|value| tone_map(*value, midpoint, slope))
+                .collect(),
+            ColorType::L8,
+        ),
+        (_, PngColor::Rgb) => (
+            buffer
+                .par_iter()
+                .flat_map_iter(|value| [tone_map(*value, midpoint, slope); 3])
+                .collect(),
+            ColorType::Rgb8,
+        ),
+    };
 
-        image::Luma([image_value])
-    });
+    let compression = match options.compression {
+        PngCompression::Default => CompressionType::Default,
+        PngCompression::Fast => CompressionType::Fast,
+        PngCompression::Best => CompressionType::Best,
+        PngCompression::Huffman => CompressionType::Huffman,
+        PngCompression::Rle => CompressionType::Rle,
+    };
+
+    let filter = match options.filter {
+        PngFilter::None => FilterType::NoFilter,
+        PngFilter::Sub => FilterType::Sub,
+        PngFilter::Up => FilterType::Up,
+        PngFilter::Avg => FilterType::Avg,
+        PngFilter::Paeth => FilterType::Paeth,
+        PngFilter::Adaptive => FilterType::Adaptive,
+    };
 
     match File::create(Path::new(image_file_path)) {
         Ok(output_file) => {
-            let frame_encoder = PngEncoder::new(BufWriter::new(output_file));
+            let frame_encoder =
+                PngEncoder::new_with_quality(BufWriter::new(output_file), compression, filter);
 
-            match frame_encoder.write_image(&frame, res_x as u32, res_y as u32, ColorType::L8) {
+            match frame_encoder.write_image(&frame, res_x as u32, res_y as u32, color_type) {
                 Ok(_) => {
                     return Ok(());
                 }
@@ -34,3 +176,119 @@ pub fn save_frame(
         }
     }
 }
+
+pub fn tone_map(mass: f32, midpoint: f32, slope: f32) -> u8 {
+    let value = mass * 255.0;
+
+    return (255.0 / (1.0 + (-slope * (value - midpoint)).exp())).round() as u8;
+}
+
+pub fn tone_map_16(mass: f32, midpoint: f32, slope: f32) -> u16 {
+    let value = mass * 255.0;
+
+    return (65535.0 / (1.0 + (-slope * (value - midpoint)).exp())).round() as u16;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tone_curve_is_centred_on_the_midpoint() {
+        assert_eq!(tone_map(170.0 / 255.0, 170.0, 0.03), 128);
+    }
+
+    #[test]
+    fn tone_curve_saturates() {
+        assert_eq!(tone_map(0.0, 170.0, 0.03), 2);
+        assert_eq!(tone_map(1.0, 170.0, 0.03), 237);
+        assert_eq!(tone_map(-10.0, 170.0, 0.03), 0);
+        assert_eq!(tone_map(10.0, 170.0, 0.03), 255);
+    }
+
+    #[test]
+    fn tone_curve_is_monotonic() {
+        let mut previous = 0;
+
+        for step in 0..=100 {
+            let value = tone_map(step as f32 / 100.0, 170.0, 0.03);
+            assert!(value >= previous);
+            previous = value;
+        }
+    }
+
+    #[test]
+    fn saved_frame_contains_tone_mapped_values() {
+        let path = std::env::temp_dir().join(format!(
+            "fluid_simulation_{}_saved_frame.png",
+            std::process::id()
+        ));
+        let buffer = vec![0.0, 0.25, 0.5, 0.75, 1.0, 170.0 / 255.0];
+
+        save_frame(
+            path.to_str().unwrap(),
+            &buffer,
+            3,
+            2,
+            170.0,
+            0.03,
+            &PngOptions::default(),
+        )
+        .unwrap();
+        let frame = image::open(&path).unwrap().into_luma8();
+
+        assert_eq!(frame.dimensions(), (3, 2));
+        for (pixel, value) in frame.pixels().zip(buffer.iter()) {
+            assert_eq!(pixel[0], tone_map(*value, 170.0, 0.03));
+        }
+    }
+
+    #[test]
+    fn sixteen_bit_tone_curve_refines_the_eight_bit_one() {
+        for step in 0..=100 {
+            let mass = step as f32 / 100.0;
+            let coarse = tone_map(mass, 170.0, 0.03) as f32;
+            let fine = tone_map_16(mass, 170.0, 0.03) as f32 / 257.0;
+
+            assert!((coarse - fine).abs() <= 0.5);
+        }
+    }
+
+    #[test]
+    fn saved_frame_honours_bit_depth_and_color() {
+        let buffer = vec![0.0, 0.25, 0.5, 0.75, 1.0, 170.0 / 255.0];
+
+        for (bit_depth, color, expected) in [
+            (8, PngColor::Rgb, ColorType::Rgb8),
+            (16, PngColor::Gray, ColorType::L16),
+            (16, PngColor::Rgb, ColorType::Rgb16),
+        ] {
+            let path = std::env::temp_dir().join(format!(
+                "fluid_simulation_{}_saved_frame_{}_{:?}.png",
+                std::process::id(),
+                bit_depth,
+                color
+            ));
+            let options = PngOptions {
+                compression: PngCompression::Best,
+                filter: PngFilter::Paeth,
+                bit_depth,
+                color,
+            };
+
+            save_frame(path.to_str().unwrap(), &buffer, 3, 2, 170.0, 0.03, &options).unwrap();
+            let frame = image::open(&path).unwrap();
+
+            assert_eq!(frame.color(), expected);
+            if bit_depth == 16 {
+                for (pixel, value) in frame.to_luma16().pixels().zip(buffer.iter()) {
+                    assert_eq!(pixel[0], tone_map_16(*value, 170.0, 0.03));
+                }
+            } else {
+                for (pixel, value) in frame.to_rgb8().pixels().zip(buffer.iter()) {
+                    assert_eq!(pixel[1], tone_map(*value, 170.0, 0.03));
+                }
+            }
+        }
+    }
+}
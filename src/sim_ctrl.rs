@@ -1,267 +1,909 @@
-use crate::flow_field::generate_flow_field;
+use crate::animation::{scale_flow_field, AnimatedParameter, Animation};
+use crate::buoyancy::Buoyancy;
+use crate::config_file::{configuration_format, parse_configuration};
+use crate::decay::Decay;
+use crate::dynamic_flow::DynamicFlowField;
+use crate::error_kind::{classified, ErrorKind};
+use crate::frame_output::{FrameOutput, OutputFormat};
+use crate::frame_pipeline::FramePipeline;
 use crate::mass_distr::load_mass_distribution;
-use crate::save_frame::save_frame;
+use crate::navier_stokes::{NavierStokesSolver, VelocityMode};
+use crate::output_directory::{prepare_output_directory, OutputPolicy};
+use crate::particles::{seed_particles, Particles};
+use crate::progress::{MassStatistics, Progress, ProgressMode};
+use crate::reaction_diffusion::ReactionDiffusion;
+use crate::save_frame::ToneCurve;
 use crate::simulate::simulate;
-use crate::utility::{Configuration, Vec2D};
+use crate::streamlines::{
+    parse_color, streamlines, LineFrame, LineStyle, Pathlines, VelocityRendering,
+};
+use crate::utility::{
+    grid_dimensions, Configuration, DynamicMode, FlowField, SUPPORTED_RESOLUTIONS,
+};
+use crate::vorticity::VorticityConfinement;
 
 use console::style;
-use indicatif::{ProgressBar, ProgressStyle};
 use rand::{
     distributions::{Distribution, Uniform},
-    thread_rng,
+    rngs::StdRng,
+    SeedableRng,
 };
-use serde_json;
-use std::{error::Error, fs::File, path::Path};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::{error::Error, fs, path::Path, sync::Arc};
+
+const DYNAMIC_MODE_EVALUATION_STEPS: usize = 16;
+
+pub fn load_configuration(configuration_file_path: &str) -> Result<Configuration, Box<dyn Error>> {
+    let format = configuration_format(configuration_file_path);
 
-pub fn run(configuration_file_path: &str) -> Result<(), Box<dyn Error>> {
-    let config_file = match File::open(Path::new(configuration_file_path)) {
-        Ok(file) => file,
+    let contents = match fs::read_to_string(Path::new(configuration_file_path)) {
+        Ok(contents) => contents,
         Err(error) => {
-            return Err(format!(
-                "Configuration File Error: cannot open configuration file! \
+            return Err(classified(
+                ErrorKind::Configuration,
+                format!(
+                    "Configuration File Error: cannot open configuration file! \
             Details: {}",
-                error
-            )
-            .into());
+                    error
+                ),
+            ));
         }
     };
 
-    let config: Configuration = match serde_json::from_reader(config_file) {
+    let config: Configuration = match parse_configuration(&contents, format) {
         Ok(config) => config,
         Err(error) => {
-            return Err(format!(
-                "Configuration File Error: contents of the configuration file cannot be read! \
+            return Err(classified(
+                ErrorKind::Configuration,
+                format!(
+                    "Configuration File Error: contents of the configuration file cannot be read! \
             Details: {}",
-                error
-            )
-            .into());
+                    error
+                ),
+            ));
         }
     };
 
+    return Ok(config);
+}
+
+/// Returns the directory the frames were written to, which differs from the configured one
+/// under the 'timestamped' output policy.
+pub fn run(mut config: Configuration) -> Result<String, Box<dyn Error>> {
     match config.check() {
         Ok(_) => {}
         Err(error) => {
-            return Err(error.into());
+            return Err(error);
         }
     };
 
-    println!(
-        "{}\n  - mass_distr_file_path:   {}\n  - output_directory_path:  {}\n  - frames_number:          {}\
-        \n  - simulation_factor:      {}\n  - target_resolution:      {}\n  - flow_field_scale:       {}\
-        \n  - dynamize_flow_field:    {}\n  - randomize_flow_field:   {}\n",
-        style("Starting the simulation with the following parameters:")
-            .bold()
-            .underlined()
-            .green(),
-        style(format!("\'{}\'", config.mass_distr_file_path)).bold().blue(),
-        style(format!("\'{}\'", config.output_directory_path)).bold().blue(),
-        style(&config.frames_number).bold().blue(),
-        style(format!("{}x", config.simulation_factor)).bold().blue(),
-        style(format!("{}p", config.target_resolution)).bold().blue(),
-        style(&config.flow_field_scale).bold().blue(),
-        style(&config.dynamize_flow_field).bold().blue(),
-        style(&config.randomize_flow_field).bold().blue()
-    );
+    config.output_directory_path =
+        prepare_output_directory(&config.output_directory_path, config.output_policy)?;
 
-    let res_x: usize;
-    let res_y: usize;
+    let solver_pool = Arc::new(build_thread_pool(config.threads, "solver")?);
+    let encoder_pool = match config.encoder_threads {
+        Some(threads) => Arc::new(build_thread_pool(Some(threads), "encoder")?),
+        None => Arc::clone(&solver_pool),
+    };
 
-    match config.target_resolution {
-        480 => {
-            res_x = 640;
-            res_y = 460;
-        }
-        720 => {
-            res_x = 1280;
-            res_y = 720;
+    // JSON progress keeps stdout machine-readable.
+    if config.progress != ProgressMode::Json {
+        println!(
+            "{}\n  - mass_distr_file_path:   {}\n  - output_directory_path:  {}\n  - frames_number:          {}\
+            \n  - simulation_factor:      {}\n  - target_resolution:      {}\n  - flow_field_scale:       {}\
+            \n  - dynamize_flow_field:    {}\n  - randomize_flow_field:   {}\n  - dynamic_mode:           {}\
+            \n  - velocity_mode:          {}\n  - vorticity_confinement:  {}\n  - buoyancy:               {}\
+            \n  - decay:                  {}\n  - reaction_diffusion:     {}\n  - particles:              {}\
+            \n  - velocity_rendering:     {}\n  - parameter_tracks:       {}\
+            \n  - threads:                {}\n  - encoder_threads:        {}\n  - encoder_workers:        {}\
+            \n  - encoder_queue_size:     {}\n  - output_policy:          {}\n  - output_format:          {}\
+            \n  - png_format:             {}\n",
+            style("Starting the simulation with the following parameters:")
+                .bold()
+                .underlined()
+                .green(),
+            style(format!("\'{}\'", config.mass_distr_file_path)).bold().blue(),
+            style(format!("\'{}\'", config.output_directory_path)).bold().blue(),
+            style(&config.frames_number).bold().blue(),
+            style(format!("{}x", config.simulation_factor)).bold().blue(),
+            style(format!("{}p", config.target_resolution)).bold().blue(),
+            style(&config.flow_field_scale).bold().blue(),
+            style(&config.dynamize_flow_field).bold().blue(),
+            style(&config.randomize_flow_field).bold().blue(),
+            style(&config.dynamic_mode).bold().blue(),
+            style(match config.velocity_mode {
+                VelocityMode::CurlNoise => config.velocity_mode.to_string(),
+                VelocityMode::NavierStokes => format!(
+                    "{} (viscosity: {}, pressure iterations: {}, noise forcing: {})",
+                    config.velocity_mode,
+                    config.viscosity,
+                    config.pressure_iterations,
+                    config.noise_forcing
+                ),
+            })
+            .bold()
+            .blue(),
+            style(&config.vorticity_confinement).bold().blue(),
+            style(
+                if config.velocity_mode == VelocityMode::NavierStokes
+                    && (config.buoyancy > 0.0 || config.density_weight > 0.0)
+                {
+                    format!(
+                        "{} (density weight: {}, gravity direction: {:?}, cooling rate: {})",
+                        config.buoyancy,
+                        config.density_weight,
+                        config.gravity_direction,
+                        config.cooling_rate
+                    )
+                } else {
+                    String::from("off")
+                }
+            )
+            .bold()
+            .blue(),
+            style(if config.decay_rate > 0.0 || config.decay_threshold > 0.0 {
+                format!(
+                    "rate: {}, threshold: {}{}",
+                    config.decay_rate,
+                    config.decay_threshold,
+                    match &config.decay_map_file_path {
+                        Some(path) => format!(", map: \'{}\'", path),
+                        None => String::new(),
+                    }
+                )
+            } else {
+                String::from("off")
+            })
+            .bold()
+            .blue(),
+            style(if config.reaction_diffusion == true {
+                format!(
+                    "on (feed: {}, kill: {}, diffusion: {} / {}, {} step(s) per step)",
+                    config.feed_rate,
+                    config.kill_rate,
+                    config.diffusion_u,
+                    config.diffusion_v,
+                    config.reaction_steps
+                )
+            } else {
+                String::from("off")
+            })
+            .bold()
+            .blue(),
+            style(if config.particles_number > 0 {
+                format!(
+                    "{} ({} seeding, {}, {} {}{})",
+                    config.particles_number,
+                    config.particle_seeding,
+                    config.particle_integrator,
+                    config.particle_rendering,
+                    config.particle_layer,
+                    if config.particle_csv == true {
+                        ", particles.csv"
+                    } else {
+                        ""
+                    }
+                )
+            } else {
+                String::from("off")
+            })
+            .bold()
+            .blue(),
+            style(match config.velocity_rendering {
+                VelocityRendering::None => String::from("none"),
+                _ => format!(
+                    "{} as .{} (spacing: {}, width: {}, colour: {})",
+                    config.velocity_rendering,
+                    config.line_format,
+                    config.line_spacing,
+                    config.line_width,
+                    config.line_color
+                ),
+            })
+            .bold()
+            .blue(),
+            style(if config.parameter_tracks.is_empty() {
+                String::from("none")
+            } else {
+                config
+                    .parameter_tracks
+                    .iter()
+                    .map(|track| format!("{} ({} keyframe(s))", track.parameter, track.keyframes.len()))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            })
+            .bold()
+            .blue(),
+            style(solver_pool.current_num_threads()).bold().blue(),
+            style(match config.encoder_threads {
+                Some(threads) => threads.to_string(),
+                None => String::from("shared with the solver"),
+            })
+            .bold()
+            .blue(),
+            style(&config.encoder_workers).bold().blue(),
+            style(&config.encoder_queue_size).bold().blue(),
+            style(&config.output_policy).bold().blue(),
+            style(&config.output_format).bold().blue(),
+            style(config.png_options()).bold().blue()
+        );
+    }
+
+    let (res_x, res_y) = match grid_dimensions(config.target_resolution) {
+        Some(dimensions) => dimensions,
+        None => {
+            unreachable!();
         }
-        1080 => {
-            res_x = 1920;
-            res_y = 1080;
+    };
+
+    let mut mass_distr = match load_mass_distribution(&config.mass_distr_file_path, res_x, res_y) {
+        Ok(mass_distr) => mass_distr,
+        Err(error) => {
+            return Err(error);
         }
-        1440 => {
-            res_x = 2560;
-            res_y = 1440;
+    };
+
+    let obstacles = match &config.obstacle_mask_file_path {
+        Some(path) if config.velocity_mode == VelocityMode::NavierStokes => {
+            match load_mass_distribution(path, res_x, res_y) {
+                Ok(mask) => Some(mask.iter().map(|value| *value > 0.5).collect::<Vec<bool>>()),
+                Err(error) => {
+                    return Err(error);
+                }
+            }
         }
-        2160 => {
-            res_x = 3840;
-            res_y = 2160;
+        _ => None,
+    };
+
+    let mut temperature = match &config.temperature_file_path {
+        _ if config.velocity_mode != VelocityMode::NavierStokes
+            || (config.buoyancy > 0.0 || config.density_weight > 0.0) == false =>
+        {
+            None
         }
-        _ => {
-            unreachable!();
+        Some(path) => match load_mass_distribution(path, res_x, res_y) {
+            Ok(temperature) => Some(temperature),
+            Err(error) => {
+                return Err(error);
+            }
+        },
+        None => Some(mass_distr.clone()),
+    };
+
+    if let Some(obstacles) = &obstacles {
+        for (index, solid) in obstacles.iter().enumerate() {
+            if *solid {
+                mass_distr[index] = 0.0;
+
+                if let Some(temperature) = &mut temperature {
+                    temperature[index] = 0.0;
+                }
+            }
         }
     }
 
-    let mut mass_distr = match load_mass_distribution(&config.mass_distr_file_path, res_x, res_y) {
-        Ok(mass_distr) => mass_distr,
+    let mut buoyancy = temperature.map(|temperature| {
+        Buoyancy::new(
+            res_x,
+            res_y,
+            config.gravity_direction,
+            config.buoyancy,
+            config.density_weight,
+            config.cooling_rate,
+            temperature,
+        )
+    });
+
+    let mut mass_buffer: Vec<f32> = vec![0.0; res_x * res_y];
+    let mut flow_field = FlowField::new(res_x * res_y);
+
+    let (offset_x, offset_y, offset_z) = if config.randomize_flow_field == true {
+        let mut rng = match config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let distr = Uniform::new(-5.0, 5.0);
+
+        (
+            distr.sample(&mut rng),
+            distr.sample(&mut rng),
+            distr.sample(&mut rng),
+        )
+    } else {
+        (0.0, 0.0, 0.0)
+    };
+
+    let flow_field_mode = if config.dynamize_flow_field == true {
+        config.dynamic_mode
+    } else {
+        DynamicMode::Full
+    };
+
+    let animation = Animation::new(&config.parameter_tracks);
+    let steps_number = config.frames_number * config.simulation_factor;
+    let step_frame = |step: usize| step as f64 / config.simulation_factor as f64;
+    let mut flow_field_scale = animation.value(
+        AnimatedParameter::FlowFieldScale,
+        0.0,
+        config.flow_field_scale,
+    );
+
+    let mut dynamic_flow_field = DynamicFlowField::new(
+        flow_field_mode,
+        res_x,
+        res_y,
+        flow_field_scale,
+        offset_x,
+        offset_y,
+        offset_z,
+        config.keyframe_interval,
+        config.coarse_factor,
+    );
+
+    if animation.animates(AnimatedParameter::EvolutionSpeed) {
+        let speeds: Vec<f64> = (0..steps_number)
+            .map(|step| animation.value(AnimatedParameter::EvolutionSpeed, step_frame(step), 1.0))
+            .collect();
+
+        dynamic_flow_field.set_evolution_speeds(&speeds);
+    }
+
+    let progress = Progress::new(config.progress, config.frames_number, config.run.clone());
+
+    let frame_output = match FrameOutput::new(
+        &config.output_directory_path,
+        config.output_format,
+        config.png_options(),
+        config.frames_number,
+        res_x,
+        res_y,
+    ) {
+        Ok(frame_output) => frame_output,
         Err(error) => {
-            return Err(error.into());
+            return Err(error);
         }
     };
 
-    let mut mass_buffer: Vec<f32> = vec![0.0; res_x * res_y];
-    let mut flow_field: Vec<Vec2D> = vec![Vec2D { x: 0.0, y: 0.0 }; res_x * res_y];
-    let mut noise_buffer: Vec<f32> = vec![0.0; res_x * res_y];
-
-    let bar = ProgressBar::new(config.frames_number as u64);
-    bar.set_style(
-        ProgressStyle::default_bar()
-            .template("[{elapsed_precise}] [{bar:70.cyan/blue}] {pos:>7}/{len:7} {msg}")
-            .progress_chars("#>-"),
+    let mut frame_pipeline = FramePipeline::new(
+        config.encoder_workers,
+        config.encoder_queue_size,
+        encoder_pool,
+        frame_output,
     );
 
-    if config.randomize_flow_field == true {
-        let mut rng = thread_rng();
-        let distr = Uniform::new(-5.0, 5.0);
+    let mut navier_stokes = match config.velocity_mode {
+        VelocityMode::CurlNoise => None,
+        VelocityMode::NavierStokes => Some(NavierStokesSolver::new(
+            res_x,
+            res_y,
+            config.viscosity,
+            config.pressure_iterations,
+            config.vorticity_confinement,
+            obstacles.as_ref(),
+        )),
+    };
 
-        let offset_x = distr.sample(&mut rng);
-        let offset_y = distr.sample(&mut rng);
-        let offset_z = distr.sample(&mut rng);
-
-        if config.dynamize_flow_field == true {
-            for frame in 0..config.frames_number {
-                for step in 0..config.simulation_factor {
-                    generate_flow_field(
-                        &mut flow_field,
-                        &mut noise_buffer,
-                        res_x,
-                        res_y,
-                        config.flow_field_scale,
-                        offset_x,
-                        offset_y,
-                        offset_z + (frame * config.simulation_factor + step) as f64 * 0.3,
-                    );
+    // The Navier-Stokes solver applies the confinement itself, on its own velocity.
+    let mut vorticity_confinement = if navier_stokes.is_none() && config.vorticity_confinement > 0.0
+    {
+        Some(VorticityConfinement::new(
+            res_x,
+            res_y,
+            config.vorticity_confinement,
+        ))
+    } else {
+        None
+    };
 
-                    simulate(&flow_field, &mut mass_distr, &mut mass_buffer, res_x, res_y)
-                }
+    if config.dynamize_flow_field == false || navier_stokes.is_some() {
+        solver_pool.install(|| dynamic_flow_field.update(&mut flow_field, 0));
+    }
+    if let (None, Some(vorticity_confinement)) = (&navier_stokes, &mut vorticity_confinement) {
+        if config.dynamize_flow_field == false {
+            solver_pool.install(|| vorticity_confinement.apply(&mut flow_field));
+        }
+    }
+    if let Some(solver) = &mut navier_stokes {
+        solver_pool.install(|| solver.seed(&flow_field));
+    }
 
-                match save_frame(
-                    &format!("{}/frame_{}.png", config.output_directory_path, frame),
-                    &mass_distr,
-                    res_x,
-                    res_y,
-                    170.0,
-                    0.03,
-                ) {
-                    Ok(_) => {}
-                    Err(error) => {
-                        return Err(error.into());
-                    }
+    let mut particles = if config.particles_number > 0 {
+        let mut rng = match config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let positions = seed_particles(
+            config.particle_seeding,
+            config.particles_number,
+            &mass_distr,
+            res_x,
+            res_y,
+            &mut rng,
+        );
+
+        Some(Particles::new(
+            res_x,
+            res_y,
+            positions,
+            config.particle_integrator,
+            config.particle_rendering,
+            config.particle_layer,
+            config.particle_trail_fade,
+        ))
+    } else {
+        None
+    };
+
+    if let Some(particles) = &mut particles {
+        if config.particle_csv == true {
+            let path = format!("{}/particles.csv", config.output_directory_path);
+
+            match particles.record_trajectories(&path) {
+                Ok(_) => {}
+                Err(error) => {
+                    return Err(error);
                 }
+            }
+        }
+    }
+    let mut frame_buffer: Vec<f32> = vec![0.0; res_x * res_y];
 
-                bar.inc(1);
+    let decay_map = match &config.decay_map_file_path {
+        Some(path) => match load_mass_distribution(path, res_x, res_y) {
+            Ok(decay_map) => Some(decay_map),
+            Err(error) => {
+                return Err(error);
             }
+        },
+        None => None,
+    };
+    let mut decay = if config.decay_rate > 0.0
+        || config.decay_threshold > 0.0
+        || animation.animates(AnimatedParameter::DecayRate)
+    {
+        Some(Decay::new(
+            res_x,
+            res_y,
+            animation.value(AnimatedParameter::DecayRate, 0.0, config.decay_rate as f64) as f32,
+            config.decay_threshold,
+            decay_map.as_ref(),
+        ))
+    } else {
+        None
+    };
 
-            bar.finish();
-        } else {
-            generate_flow_field(
-                &mut flow_field,
-                &mut noise_buffer,
-                res_x,
-                res_y,
+    let mut reaction_diffusion = if config.reaction_diffusion == true {
+        Some(ReactionDiffusion::new(
+            res_x,
+            res_y,
+            config.feed_rate,
+            config.kill_rate,
+            config.diffusion_u,
+            config.diffusion_v,
+            config.reaction_steps,
+            &mass_distr,
+        ))
+    } else {
+        None
+    };
+
+    let mut pathlines = match config.velocity_rendering {
+        VelocityRendering::Pathlines => Some(Pathlines::new(
+            res_x,
+            res_y,
+            config.line_spacing,
+            config.pathline_length,
+        )),
+        _ => None,
+    };
+    let line_style = LineStyle {
+        width: config.line_width,
+        color: parse_color(&config.line_color).unwrap_or([255, 255, 255]),
+    };
+
+    let mut scaled_velocity = if animation.animates(AnimatedParameter::FlowSpeed) {
+        FlowField::new(res_x * res_y)
+    } else {
+        FlowField::new(0)
+    };
+
+    for frame in 0..config.frames_number {
+        let frame_time = frame as f64;
+
+        if animation.animates(AnimatedParameter::FlowFieldScale) {
+            let scale = animation.value(
+                AnimatedParameter::FlowFieldScale,
+                frame_time,
                 config.flow_field_scale,
-                offset_x,
-                offset_y,
-                offset_z,
             );
 
-            for frame in 0..config.frames_number {
-                for _ in 0..config.simulation_factor {
-                    simulate(&flow_field, &mut mass_distr, &mut mass_buffer, res_x, res_y)
-                }
+            // A dynamic field picks up the new scale on its next update; a static one is
+            // generated again.
+            if scale != flow_field_scale {
+                flow_field_scale = scale;
+                dynamic_flow_field.set_scale(scale);
 
-                match save_frame(
-                    &format!("{}/frame_{}.png", config.output_directory_path, frame),
-                    &mass_distr,
-                    res_x,
-                    res_y,
-                    170.0,
-                    0.03,
-                ) {
-                    Ok(_) => {}
-                    Err(error) => {
-                        return Err(error.into());
-                    }
-                }
+                if config.dynamize_flow_field == false {
+                    solver_pool.install(|| {
+                        dynamic_flow_field.update(&mut flow_field, 0);
 
-                bar.inc(1);
+                        if let (None, Some(vorticity_confinement)) =
+                            (&navier_stokes, &mut vorticity_confinement)
+                        {
+                            vorticity_confinement.apply(&mut flow_field);
+                        }
+                    });
+                }
             }
         }
 
-        bar.finish();
-    } else {
-        if config.dynamize_flow_field == true {
-            for frame in 0..config.frames_number {
-                for step in 0..config.simulation_factor {
-                    generate_flow_field(
-                        &mut flow_field,
-                        &mut noise_buffer,
-                        res_x,
-                        res_y,
-                        config.flow_field_scale,
-                        0.0,
-                        0.0,
-                        (frame * config.simulation_factor + step) as f64 * 0.3,
+        if let (true, Some(decay)) = (animation.animates(AnimatedParameter::DecayRate), &mut decay)
+        {
+            decay.set_decay_rate(animation.value(
+                AnimatedParameter::DecayRate,
+                frame_time,
+                config.decay_rate as f64,
+            ) as f32);
+        }
+
+        solver_pool.install(|| {
+            for step in 0..config.simulation_factor {
+                let step = frame * config.simulation_factor + step;
+
+                let velocity = match &mut navier_stokes {
+                    None => {
+                        if config.dynamize_flow_field == true {
+                            dynamic_flow_field.update(&mut flow_field, step);
+
+                            if let Some(vorticity_confinement) = &mut vorticity_confinement {
+                                vorticity_confinement.apply(&mut flow_field);
+                            }
+                        }
+
+                        &flow_field
+                    }
+                    Some(solver) => {
+                        // The curl noise only drives the fluid through the forcing term.
+                        let noise_forcing = animation.value(
+                            AnimatedParameter::NoiseForcing,
+                            step_frame(step),
+                            config.noise_forcing as f64,
+                        ) as f32;
+                        let force = if noise_forcing > 0.0 {
+                            if config.dynamize_flow_field == true {
+                                dynamic_flow_field.update(&mut flow_field, step);
+                            }
+                            Some((&flow_field, noise_forcing))
+                        } else {
+                            None
+                        };
+
+                        if let Some(buoyancy) = &buoyancy {
+                            buoyancy.apply(solver.velocity_mut(), &mass_distr);
+                        }
+
+                        solver.step(force);
+
+                        solver.velocity()
+                    }
+                };
+
+                // The upwind advection is only stable up to unit speed, so faster flows are
+                // split into sub-steps.
+                let flow_speed =
+                    animation.value(AnimatedParameter::FlowSpeed, step_frame(step), 1.0);
+                let substeps = (flow_speed.ceil() as usize).max(1);
+                let fraction = 1.0 / substeps as f32;
+                let velocity = if flow_speed != 1.0 {
+                    scale_flow_field(
+                        &mut scaled_velocity,
+                        velocity,
+                        (flow_speed / substeps as f64) as f32,
                     );
+                    &scaled_velocity
+                } else {
+                    velocity
+                };
 
-                    simulate(&flow_field, &mut mass_distr, &mut mass_buffer, res_x, res_y)
-                }
+                for _ in 0..substeps {
+                    simulate(velocity, &mut mass_distr, &mut mass_buffer, res_x, res_y);
 
-                match save_frame(
-                    &format!("{}/frame_{}.png", config.output_directory_path, frame),
-                    &mass_distr,
-                    res_x,
-                    res_y,
-                    170.0,
-                    0.03,
-                ) {
-                    Ok(_) => {}
-                    Err(error) => {
-                        return Err(error.into());
+                    if let Some(buoyancy) = &mut buoyancy {
+                        buoyancy.advect(velocity, fraction);
+                    }
+
+                    if let Some(reaction_diffusion) = &mut reaction_diffusion {
+                        reaction_diffusion.advance(velocity, &mut mass_distr, fraction);
+                    }
+
+                    if let Some(particles) = &mut particles {
+                        particles.advance(velocity);
+                    }
+
+                    if let Some(pathlines) = &mut pathlines {
+                        pathlines.advance(velocity);
                     }
                 }
 
-                bar.inc(1);
+                if let Some(decay) = &decay {
+                    decay.apply(&mut mass_distr);
+                }
             }
+        });
+
+        let mass = solver_pool.install(|| MassStatistics::new(&mass_distr));
+
+        if mass.is_finite() == false {
+            return Err(classified(
+                ErrorKind::Numerical,
+                format!(
+                    "Numerical Error: the mass distribution is not finite in frame {}!",
+                    frame
+                ),
+            ));
+        }
+
+        if config.velocity_rendering != VelocityRendering::None {
+            let lines = match &pathlines {
+                Some(pathlines) => pathlines.lines(),
+                None => {
+                    let velocity = match &navier_stokes {
+                        Some(solver) => solver.velocity(),
+                        None => &flow_field,
+                    };
 
-            bar.finish();
-        } else {
-            generate_flow_field(
-                &mut flow_field,
-                &mut noise_buffer,
+                    streamlines(velocity, res_x, res_y, config.line_spacing)
+                }
+            };
+            let line_frame = LineFrame {
+                path: format!(
+                    "{}/{}_{}.{}",
+                    config.output_directory_path,
+                    config.velocity_rendering,
+                    frame,
+                    config.line_format
+                ),
+                lines,
+                format: config.line_format,
                 res_x,
                 res_y,
-                config.flow_field_scale,
-                0.0,
-                0.0,
-                0.0,
-            );
+                style: line_style.clone(),
+            };
 
-            for frame in 0..config.frames_number {
-                for _ in 0..config.simulation_factor {
-                    simulate(&flow_field, &mut mass_distr, &mut mass_buffer, res_x, res_y)
+            match frame_pipeline.submit_lines(line_frame) {
+                Ok(_) => {}
+                Err(error) => {
+                    return Err(error);
                 }
+            }
+        }
 
-                match save_frame(
-                    &format!("{}/frame_{}.png", config.output_directory_path, frame),
-                    &mass_distr,
-                    res_x,
-                    res_y,
-                    170.0,
-                    0.03,
-                ) {
+        let tone_curve = config.tone_curve();
+        let tone_curve = ToneCurve {
+            midpoint: animation.value(
+                AnimatedParameter::ToneMidpoint,
+                frame_time,
+                tone_curve.midpoint as f64,
+            ) as f32,
+            slope: animation.value(
+                AnimatedParameter::ToneSlope,
+                frame_time,
+                tone_curve.slope as f64,
+            ) as f32,
+        };
+        let submitted = match &mut particles {
+            Some(particles) => {
+                match particles.write_trajectories(frame) {
                     Ok(_) => {}
                     Err(error) => {
-                        return Err(error.into());
+                        return Err(error);
                     }
                 }
 
-                bar.inc(1);
+                solver_pool.install(|| particles.render(&mass_distr, &mut frame_buffer));
+                frame_pipeline.submit(frame, &frame_buffer, tone_curve)
+            }
+            None => frame_pipeline.submit(frame, &mass_distr, tone_curve),
+        };
+
+        match submitted {
+            Ok(_) => {}
+            Err(error) => {
+                return Err(error);
             }
+        }
+
+        progress.frame(frame, &mass);
+    }
+
+    if let Some(particles) = &mut particles {
+        match particles.finish() {
+            Ok(_) => {}
+            Err(error) => {
+                return Err(error);
+            }
+        }
+    }
+
+    match frame_pipeline.finish() {
+        Ok(_) => {}
+        Err(error) => {
+            return Err(error);
+        }
+    }
+
+    progress.finish();
+
+    return Ok(config.output_directory_path);
+}
+
+pub fn preview(mut config: Configuration) -> Result<String, Box<dyn Error>> {
+    config.output_directory_path = format!("{}/preview", config.output_directory_path);
+    config.create_output_directory = true;
+    config.output_policy = OutputPolicy::Overwrite;
+    config.output_format = OutputFormat::Png;
+
+    return run(config);
+}
+
+pub fn info(config: Option<&Configuration>) {
+    println!(
+        "{}\n  - available threads:      {}\n  - target resolutions:     {}\
+        \n  - dynamic modes:          full, keyframes, coarse\
+        \n  - velocity modes:         curl_noise, navier_stokes\
+        \n  - output formats:         png, exr, tiff, npy, npy_stack\n",
+        style(format!("fluid_simulation {}", env!("CARGO_PKG_VERSION")))
+            .bold()
+            .underlined()
+            .green(),
+        style(rayon::current_num_threads()).bold().blue(),
+        style(
+            SUPPORTED_RESOLUTIONS
+                .iter()
+                .map(|resolution| {
+                    let (res_x, res_y) = grid_dimensions(*resolution).unwrap();
+                    format!("{}p ({}x{})", resolution, res_x, res_y)
+                })
+                .collect::<Vec<String>>()
+                .join(", ")
+        )
+        .bold()
+        .blue()
+    );
+
+    let config = match config {
+        Some(config) => config,
+        None => {
+            return;
+        }
+    };
+
+    let checked = config.check();
+    let check = match &checked {
+        Ok(_) => String::from("passed"),
+        Err(error) => error.to_string(),
+    };
+    let (res_x, res_y) = grid_dimensions(config.target_resolution).unwrap_or((0, 0));
+    let raw_frame_size = (res_x * res_y * std::mem::size_of::<f32>()) as f64 / 1048576.0;
+
+    println!(
+        "{}\n  - grid:                   {}\n  - simulation steps:       {}\
+        \n  - solver threads:         {}\n  - encoder threads:        {}\
+        \n  - output format:          {}\n  - raw frame size:         {}\
+        \n  - raw output size:        {}\n  - configuration check:    {}\n",
+        style("Configuration:").bold().underlined().green(),
+        style(format!("{}x{}", res_x, res_y)).bold().blue(),
+        style(config.frames_number * config.simulation_factor)
+            .bold()
+            .blue(),
+        style(match config.threads {
+            Some(threads) => threads.to_string(),
+            None => format!("all available ({})", rayon::current_num_threads()),
+        })
+        .bold()
+        .blue(),
+        style(match config.encoder_threads {
+            Some(threads) => threads.to_string(),
+            None => String::from("shared with the solver"),
+        })
+        .bold()
+        .blue(),
+        style(&config.output_format).bold().blue(),
+        style(format!("{:.2} MiB", raw_frame_size)).bold().blue(),
+        style(format!(
+            "{:.2} MiB",
+            raw_frame_size * config.frames_number as f64
+        ))
+        .bold()
+        .blue(),
+        style(check).bold().blue()
+    );
 
-            bar.finish();
+    if checked.is_ok()
+        && config.dynamize_flow_field == true
+        && config.dynamic_mode != DynamicMode::Full
+    {
+        if let Err(error) = evaluate_dynamic_mode(config) {
+            println!("{}\n", style(error.to_string()).bold().red());
         }
     }
+}
+
+/// Compares the configured dynamic mode with full regeneration on the configured mass
+/// distribution.
+fn evaluate_dynamic_mode(config: &Configuration) -> Result<(), Box<dyn Error>> {
+    let (res_x, res_y) = match grid_dimensions(config.target_resolution) {
+        Some(dimensions) => dimensions,
+        None => {
+            return Ok(());
+        }
+    };
+
+    let mass_distr = match load_mass_distribution(&config.mass_distr_file_path, res_x, res_y) {
+        Ok(mass_distr) => mass_distr,
+        Err(error) => {
+            return Err(error);
+        }
+    };
+
+    let mut dynamic_flow_field = DynamicFlowField::new(
+        config.dynamic_mode,
+        res_x,
+        res_y,
+        config.flow_field_scale,
+        0.0,
+        0.0,
+        0.0,
+        config.keyframe_interval,
+        config.coarse_factor,
+    );
+    let report = dynamic_flow_field.evaluate(&mass_distr, DYNAMIC_MODE_EVALUATION_STEPS);
+
+    println!(
+        "{}\n  - speed-up:               {}\n  - flow field RMS error:   {}\
+        \n  - flow field max error:   {}\n  - mass RMS error:         {}\n",
+        style(format!(
+            "Dynamic mode \'{}\' compared with full regeneration ({} steps):",
+            config.dynamic_mode, DYNAMIC_MODE_EVALUATION_STEPS
+        ))
+        .bold()
+        .underlined()
+        .green(),
+        style(format!("{:.2}x", report.speed_up)).bold().blue(),
+        style(format!("{:.5}", report.flow_field_rms_error))
+            .bold()
+            .blue(),
+        style(format!("{:.5}", report.flow_field_max_error))
+            .bold()
+            .blue(),
+        style(format!("{:.5}", report.mass_distr_rms_error))
+            .bold()
+            .blue()
+    );
 
     return Ok(());
 }
+
+fn build_thread_pool(
+    threads: Option<usize>,
+    name: &'static str,
+) -> Result<ThreadPool, Box<dyn Error>> {
+    match ThreadPoolBuilder::new()
+        .num_threads(threads.unwrap_or(0))
+        .thread_name(move |index| format!("{}-{}", name, index))
+        .build()
+    {
+        Ok(pool) => {
+            return Ok(pool);
+        }
+        Err(error) => {
+            return Err(classified(
+                ErrorKind::Configuration,
+                format!(
+                    "Thread Pool Error: cannot create the {} thread pool! Details: {}",
+                    name, error
+                ),
+            ));
+        }
+    }
+}
@@ -1,40 +1,211 @@
-use crate::utility::Vec2D;
+use crate::utility::FlowField;
 
 use rayon::prelude::*;
 
 pub fn simulate(
-    flow_field: &Vec<Vec2D>,
+    flow_field: &FlowField,
     mass_distr: &mut Vec<f32>,
     mass_buffer: &mut Vec<f32>,
     res_x: usize,
     res_y: usize,
 ) {
+    let mass = &mass_distr[..];
+
     mass_buffer
         .par_chunks_mut(res_x)
         .enumerate()
         .skip(1)
         .take(res_y - 2)
         .for_each(|(y, chunk)| {
-            let mut grad = Vec2D { x: 0.0, y: 0.0 };
-            let mut diff: f32;
+            let row = &mass[y * res_x..(y + 1) * res_x];
+
+            advect_row(
+                &mut chunk[1..res_x - 1],
+                &row[..res_x - 2],
+                &row[1..res_x - 1],
+                &row[2..],
+                &mass[(y - 1) * res_x + 1..y * res_x - 1],
+                &mass[(y + 1) * res_x + 1..(y + 2) * res_x - 1],
+                &flow_field.x[y * res_x + 1..(y + 1) * res_x - 1],
+                &flow_field.y[y * res_x + 1..(y + 1) * res_x - 1],
+            );
+        });
+
+    std::mem::swap(mass_distr, mass_buffer);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn advect_row(
+    output: &mut [f32],
+    left: &[f32],
+    centre: &[f32],
+    right: &[f32],
+    above: &[f32],
+    below: &[f32],
+    flow_x: &[f32],
+    flow_y: &[f32],
+) {
+    let len = output.len();
+    let (left, centre, right) = (&left[..len], &centre[..len], &right[..len]);
+    let (above, below) = (&above[..len], &below[..len]);
+    let (flow_x, flow_y) = (&flow_x[..len], &flow_y[..len]);
+
+    for i in 0..len {
+        let diff = (flow_x[i].max(0.0) * (centre[i] - left[i])
+            + flow_x[i].min(0.0) * (right[i] - centre[i]))
+            + (flow_y[i].max(0.0) * (centre[i] - above[i])
+                + flow_y[i].min(0.0) * (below[i] - centre[i]));
+
+        output[i] = centre[i] - 0.5 * diff;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flow_field::generate_flow_field;
+
+    const RES_X: usize = 64;
+    const RES_Y: usize = 48;
+
+    fn blob() -> Vec<f32> {
+        return (0..RES_X * RES_Y)
+            .map(|index| {
+                let x = (index % RES_X) as f32 - 24.0;
+                let y = (index / RES_X) as f32 - 24.0;
+
+                (-(x * x + y * y) / 20.0).exp()
+            })
+            .collect();
+    }
 
-            for (x, value) in chunk.iter_mut().enumerate().skip(1).take(res_x - 2) {
-                grad.x = if flow_field[y * res_x + x].x < 0.0 {
-                    mass_distr[y * res_x + x + 1] - mass_distr[y * res_x + x]
+    fn centre_of_mass(mass_distr: &[f32]) -> (f32, f32) {
+        let mut total = 0.0;
+        let mut centre = (0.0, 0.0);
+
+        for (index, value) in mass_distr.iter().enumerate() {
+            total += value;
+            centre.0 += value * (index % RES_X) as f32;
+            centre.1 += value * (index / RES_X) as f32;
+        }
+
+        return (centre.0 / total, centre.1 / total);
+    }
+
+    fn branching_upwind_step(flow_field: &FlowField, mass_distr: &[f32]) -> Vec<f32> {
+        let mut result = mass_distr.to_vec();
+
+        for y in 1..RES_Y - 1 {
+            for x in 1..RES_X - 1 {
+                let index = y * RES_X + x;
+                let grad_x = if flow_field.x[index] < 0.0 {
+                    mass_distr[index + 1] - mass_distr[index]
                 } else {
-                    mass_distr[y * res_x + x] - mass_distr[y * res_x + x - 1]
+                    mass_distr[index] - mass_distr[index - 1]
                 };
-
-                grad.y = if flow_field[y * res_x + x].y < 0.0 {
-                    mass_distr[(y + 1) * res_x + x] - mass_distr[y * res_x + x]
+                let grad_y = if flow_field.y[index] < 0.0 {
+                    mass_distr[index + RES_X] - mass_distr[index]
                 } else {
-                    mass_distr[y * res_x + x] - mass_distr[(y - 1) * res_x + x]
+                    mass_distr[index] - mass_distr[index - RES_X]
                 };
 
-                diff = flow_field[y * res_x + x].x * grad.x + flow_field[y * res_x + x].y * grad.y;
-                *value = mass_distr[y * res_x + x] - 0.5 * diff;
+                result[index] = mass_distr[index]
+                    - 0.5 * (flow_field.x[index] * grad_x + flow_field.y[index] * grad_y);
             }
-        });
+        }
 
-    std::mem::swap(mass_distr, mass_buffer);
+        return result;
+    }
+
+    #[test]
+    fn matches_the_branching_upwind_scheme() {
+        let mut flow_field = FlowField::new(RES_X * RES_Y);
+        let mut mass_distr = blob();
+        let mut mass_buffer = vec![0.0; RES_X * RES_Y];
+
+        generate_flow_field(
+            &mut flow_field,
+            &mut Vec::new(),
+            RES_X,
+            RES_Y,
+            10.0,
+            0.0,
+            0.0,
+            0.0,
+        );
+
+        for _ in 0..10 {
+            let expected = branching_upwind_step(&flow_field, &mass_distr);
+            simulate(&flow_field, &mut mass_distr, &mut mass_buffer, RES_X, RES_Y);
+
+            for y in 1..RES_Y - 1 {
+                for x in 1..RES_X - 1 {
+                    let index = y * RES_X + x;
+                    assert!((mass_distr[index] - expected[index]).abs() <= f32::EPSILON);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn zero_flow_field_is_the_identity() {
+        let flow_field = FlowField::new(RES_X * RES_Y);
+        let initial = blob();
+        let mut mass_distr = initial.clone();
+        let mut mass_buffer = vec![0.0; RES_X * RES_Y];
+
+        for _ in 0..10 {
+            simulate(&flow_field, &mut mass_distr, &mut mass_buffer, RES_X, RES_Y);
+        }
+
+        for y in 1..RES_Y - 1 {
+            for x in 1..RES_X - 1 {
+                assert_eq!(mass_distr[y * RES_X + x], initial[y * RES_X + x]);
+            }
+        }
+    }
+
+    #[test]
+    fn uniform_flow_field_translates_the_pattern() {
+        let flow_field = FlowField {
+            x: vec![1.0; RES_X * RES_Y],
+            y: vec![-0.5; RES_X * RES_Y],
+        };
+        let mut mass_distr = blob();
+        let mut mass_buffer = vec![0.0; RES_X * RES_Y];
+        let (initial_x, initial_y) = centre_of_mass(&mass_distr);
+
+        for _ in 0..20 {
+            simulate(&flow_field, &mut mass_distr, &mut mass_buffer, RES_X, RES_Y);
+        }
+
+        let (final_x, final_y) = centre_of_mass(&mass_distr);
+
+        assert!((final_x - initial_x - 10.0).abs() < 0.05);
+        assert!((final_y - initial_y + 5.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn mass_stays_within_initial_bounds() {
+        let mut flow_field = FlowField::new(RES_X * RES_Y);
+        let mut mass_distr = blob();
+        let mut mass_buffer = vec![0.0; RES_X * RES_Y];
+
+        generate_flow_field(
+            &mut flow_field,
+            &mut Vec::new(),
+            RES_X,
+            RES_Y,
+            10.0,
+            0.0,
+            0.0,
+            0.0,
+        );
+
+        for _ in 0..100 {
+            simulate(&flow_field, &mut mass_distr, &mut mass_buffer, RES_X, RES_Y);
+
+            assert!(mass_distr.iter().all(|value| (0.0..=1.0).contains(value)));
+        }
+    }
 }
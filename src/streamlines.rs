@@ -0,0 +1,607 @@
+use crate::error_kind::{classified, ErrorKind};
+use crate::particles::{
+    seed_particles, ParticleIntegrator, ParticleLayer, ParticleRendering, ParticleSeeding,
+    Particles,
+};
+use crate::utility::{FlowField, Vec2D};
+
+use image::{Rgb, RgbImage};
+use rand::{rngs::StdRng, SeedableRng};
+use serde::Deserialize;
+use std::{collections::VecDeque, error::Error, fmt, fs, path::Path};
+
+/// Length of an integration step of the streamlines, in cells.
+const STEP: f32 = 0.5;
+/// Speed below which a streamline is considered to have reached a stagnation point.
+const MIN_SPEED: f32 = 1e-4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VelocityRendering {
+    None,
+    Streamlines,
+    Pathlines,
+}
+
+impl Default for VelocityRendering {
+    fn default() -> Self {
+        return VelocityRendering::None;
+    }
+}
+
+impl fmt::Display for VelocityRendering {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VelocityRendering::None => write!(f, "none"),
+            VelocityRendering::Streamlines => write!(f, "streamlines"),
+            VelocityRendering::Pathlines => write!(f, "pathlines"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LineFormat {
+    Png,
+    Svg,
+}
+
+impl Default for LineFormat {
+    fn default() -> Self {
+        return LineFormat::Png;
+    }
+}
+
+impl fmt::Display for LineFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LineFormat::Png => write!(f, "png"),
+            LineFormat::Svg => write!(f, "svg"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineStyle {
+    pub width: f32,
+    pub color: [u8; 3],
+}
+
+/// A streamline or pathline frame waiting to be written.
+pub struct LineFrame {
+    pub path: String,
+    pub lines: Vec<Vec<Vec2D>>,
+    pub format: LineFormat,
+    pub res_x: usize,
+    pub res_y: usize,
+    pub style: LineStyle,
+}
+
+impl LineFrame {
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        match self.format {
+            LineFormat::Png => {
+                return save_lines_png(
+                    &self.path,
+                    &self.lines,
+                    self.res_x,
+                    self.res_y,
+                    &self.style,
+                );
+            }
+            LineFormat::Svg => {
+                return save_lines_svg(
+                    &self.path,
+                    &self.lines,
+                    self.res_x,
+                    self.res_y,
+                    &self.style,
+                );
+            }
+        }
+    }
+}
+
+/// Parses a '#rrggbb' colour.
+pub fn parse_color(color: &str) -> Option<[u8; 3]> {
+    let hex = color.strip_prefix('#')?;
+
+    if hex.len() != 6 || hex.chars().all(|digit| digit.is_ascii_hexdigit()) == false {
+        return None;
+    }
+
+    let channel = |index: usize| u8::from_str_radix(&hex[index..index + 2], 16).ok();
+
+    return Some([channel(0)?, channel(2)?, channel(4)?]);
+}
+
+/// Accepted streamline points, bucketed by cells of `spacing` x `spacing`.
+struct PointGrid {
+    spacing: f32,
+    columns: usize,
+    rows: usize,
+    cells: Vec<Vec<(Vec2D, usize, i64)>>,
+}
+
+impl PointGrid {
+    fn new(res_x: usize, res_y: usize, spacing: f32) -> PointGrid {
+        let columns = (res_x as f32 / spacing).ceil() as usize + 1;
+        let rows = (res_y as f32 / spacing).ceil() as usize + 1;
+
+        return PointGrid {
+            spacing,
+            columns,
+            rows,
+            cells: vec![Vec::new(); columns * rows],
+        };
+    }
+
+    fn cell(&self, point: &Vec2D) -> (usize, usize) {
+        return (
+            ((point.x / self.spacing) as usize).min(self.columns - 1),
+            ((point.y / self.spacing) as usize).min(self.rows - 1),
+        );
+    }
+
+    fn insert(&mut self, point: &Vec2D, line: usize, step: i64) {
+        let (column, row) = self.cell(point);
+
+        self.cells[row * self.columns + column].push((point.clone(), line, step));
+    }
+
+    /// Whether no point closer than `distance` (at most the grid spacing) belongs to another
+    /// line, or to the part of `line` more than `own_steps` steps away from `step`.
+    fn is_free(
+        &self,
+        point: &Vec2D,
+        distance: f32,
+        line: Option<usize>,
+        step: i64,
+        own_steps: i64,
+    ) -> bool {
+        let (column, row) = self.cell(point);
+
+        for row in row.saturating_sub(1)..(row + 2).min(self.rows) {
+            for column in column.saturating_sub(1)..(column + 2).min(self.columns) {
+                for (other, other_line, other_step) in &self.cells[row * self.columns + column] {
+                    let close = (other.x - point.x).powi(2) + (other.y - point.y).powi(2)
+                        < distance * distance;
+                    let own = line == Some(*other_line) && (other_step - step).abs() <= own_steps;
+
+                    if close && own == false {
+                        return false;
+                    }
+                }
+            }
+        }
+
+        return true;
+    }
+}
+
+/// Evenly spaced streamlines of `flow_field` (B. Jobard and W. Lefer, 1997): every line is
+/// traced in both directions until it comes closer than half of `spacing` to another line,
+/// and new lines are seeded at `spacing` beside the accepted ones.
+pub fn streamlines(
+    flow_field: &FlowField,
+    res_x: usize,
+    res_y: usize,
+    spacing: f32,
+) -> Vec<Vec<Vec2D>> {
+    let mut grid = PointGrid::new(res_x, res_y, spacing);
+    let mut lines: Vec<Vec<Vec2D>> = Vec::new();
+    let mut queue: VecDeque<usize> = VecDeque::new();
+    let max_steps = (4.0 * (res_x + res_y) as f32 / STEP) as i64;
+    let own_steps = (2.0 * spacing / STEP).ceil() as i64;
+
+    let direction = |point: &Vec2D| -> Option<Vec2D> {
+        let velocity = flow_field.sample(res_x, res_y, point.x, point.y);
+        let speed = velocity.length();
+
+        if speed < MIN_SPEED {
+            return None;
+        }
+        return Some(Vec2D {
+            x: velocity.x / speed,
+            y: velocity.y / speed,
+        });
+    };
+    let inside = |point: &Vec2D| {
+        point.x >= 1.0
+            && point.y >= 1.0
+            && point.x <= (res_x - 2) as f32
+            && point.y <= (res_y - 2) as f32
+    };
+
+    let trace = |seed: Vec2D, grid: &mut PointGrid, lines: &mut Vec<Vec<Vec2D>>| {
+        let line = lines.len();
+        let mut halves: [Vec<Vec2D>; 2] = [Vec::new(), Vec::new()];
+
+        grid.insert(&seed, line, 0);
+        for (half, sign) in halves.iter_mut().zip([1.0, -1.0]) {
+            let mut point = seed.clone();
+
+            for step in 1..max_steps {
+                // Midpoint (RK2) step along the normalised velocity.
+                let next = match direction(&point).and_then(|first| {
+                    let middle = Vec2D {
+                        x: point.x + 0.5 * sign * STEP * first.x,
+                        y: point.y + 0.5 * sign * STEP * first.y,
+                    };
+                    direction(&middle)
+                }) {
+                    Some(second) => Vec2D {
+                        x: point.x + sign * STEP * second.x,
+                        y: point.y + sign * STEP * second.y,
+                    },
+                    None => break,
+                };
+                let step = step * sign as i64;
+
+                if inside(&next) == false
+                    || grid.is_free(&next, 0.5 * spacing, Some(line), step, own_steps) == false
+                {
+                    break;
+                }
+
+                grid.insert(&next, line, step);
+                half.push(next.clone());
+                point = next;
+            }
+        }
+
+        let [forward, backward] = halves;
+        lines.push(
+            backward
+                .into_iter()
+                .rev()
+                .chain(std::iter::once(seed))
+                .chain(forward)
+                .collect(),
+        );
+    };
+
+    let seeds = (0..(res_y as f32 / spacing) as usize).flat_map(|row| {
+        (0..(res_x as f32 / spacing) as usize).map(move |column| Vec2D {
+            x: (column as f32 + 0.5) * spacing,
+            y: (row as f32 + 0.5) * spacing,
+        })
+    });
+    let centre = Vec2D {
+        x: 0.5 * res_x as f32,
+        y: 0.5 * res_y as f32,
+    };
+
+    // The queue grows lines beside the accepted ones; the regular seeds only start the
+    // regions that the queue cannot reach (behind stagnation points or the border).
+    for seed in std::iter::once(centre).chain(seeds) {
+        if inside(&seed) == false
+            || direction(&seed).is_none()
+            || grid.is_free(&seed, spacing, None, 0, 0) == false
+        {
+            continue;
+        }
+
+        trace(seed, &mut grid, &mut lines);
+        queue.push_back(lines.len() - 1);
+
+        while let Some(line) = queue.pop_front() {
+            let points = lines[line].clone();
+
+            for (point, next) in points.iter().zip(points.iter().skip(1)) {
+                let (dx, dy) = (next.x - point.x, next.y - point.y);
+                let length = (dx * dx + dy * dy).sqrt().max(MIN_SPEED);
+
+                for sign in [1.0, -1.0] {
+                    let candidate = Vec2D {
+                        x: point.x - sign * spacing * dy / length,
+                        y: point.y + sign * spacing * dx / length,
+                    };
+
+                    if inside(&candidate)
+                        && direction(&candidate).is_some()
+                        && grid.is_free(&candidate, spacing, None, 0, 0)
+                    {
+                        trace(candidate, &mut grid, &mut lines);
+                        queue.push_back(lines.len() - 1);
+                    }
+                }
+            }
+        }
+    }
+
+    lines.retain(|line| line.len() > 1);
+
+    return lines;
+}
+
+/// Trajectories of evenly spaced particles through a field that changes over time,
+/// limited to the last `length` steps.
+pub struct Pathlines {
+    particles: Particles,
+    length: usize,
+    history: VecDeque<Vec<Vec2D>>,
+}
+
+impl Pathlines {
+    pub fn new(res_x: usize, res_y: usize, spacing: f32, length: usize) -> Pathlines {
+        let number = ((res_x as f32 / spacing) * (res_y as f32 / spacing)).round() as usize;
+        let positions = seed_particles(
+            ParticleSeeding::Grid,
+            number.max(1),
+            &Vec::new(),
+            res_x,
+            res_y,
+            &mut StdRng::seed_from_u64(0),
+        );
+        let mut history = VecDeque::new();
+        history.push_back(positions.clone());
+
+        return Pathlines {
+            particles: Particles::new(
+                res_x,
+                res_y,
+                positions,
+                ParticleIntegrator::Rk4,
+                ParticleRendering::None,
+                ParticleLayer::Over,
+                0.0,
+            ),
+            length,
+            history,
+        };
+    }
+
+    pub fn advance(&mut self, flow_field: &FlowField) {
+        self.particles.advance(flow_field);
+        self.history.push_back(self.particles.positions().clone());
+
+        while self.history.len() > self.length + 1 {
+            self.history.pop_front();
+        }
+    }
+
+    pub fn lines(&self) -> Vec<Vec<Vec2D>> {
+        return (0..self.particles.positions().len())
+            .map(|particle| {
+                self.history
+                    .iter()
+                    .map(|positions| positions[particle].clone())
+                    .collect()
+            })
+            .collect();
+    }
+}
+
+/// Draws the lines, anti-aliased, on a black `res_x` x `res_y` image.
+pub fn save_lines_png(
+    path: &str,
+    lines: &Vec<Vec<Vec2D>>,
+    res_x: usize,
+    res_y: usize,
+    style: &LineStyle,
+) -> Result<(), Box<dyn Error>> {
+    let mut coverage: Vec<f32> = vec![0.0; res_x * res_y];
+    let radius = 0.5 * style.width;
+
+    for line in lines {
+        for (start, end) in line.iter().zip(line.iter().skip(1)) {
+            let (dx, dy) = (end.x - start.x, end.y - start.y);
+            let length_squared = (dx * dx + dy * dy).max(1e-12);
+            let min_x = (start.x.min(end.x) - radius - 1.0).floor().max(0.0) as usize;
+            let min_y = (start.y.min(end.y) - radius - 1.0).floor().max(0.0) as usize;
+            let max_x = ((start.x.max(end.x) + radius + 1.0).ceil() as usize).min(res_x - 1);
+            let max_y = ((start.y.max(end.y) + radius + 1.0).ceil() as usize).min(res_y - 1);
+
+            for y in min_y..=max_y {
+                for x in min_x..=max_x {
+                    let (px, py) = (x as f32 - start.x, y as f32 - start.y);
+                    let t = ((px * dx + py * dy) / length_squared).clamp(0.0, 1.0);
+                    let distance = ((px - t * dx).powi(2) + (py - t * dy).powi(2)).sqrt();
+                    let value = (radius + 0.5 - distance).clamp(0.0, 1.0);
+                    let pixel = &mut coverage[y * res_x + x];
+
+                    *pixel = pixel.max(value);
+                }
+            }
+        }
+    }
+
+    let image = RgbImage::from_fn(res_x as u32, res_y as u32, |x, y| {
+        let value = coverage[y as usize * res_x + x as usize];
+
+        Rgb(style
+            .color
+            .map(|channel| (channel as f32 * value).round() as u8))
+    });
+
+    match image.save(Path::new(path)) {
+        Ok(_) => {
+            return Ok(());
+        }
+        Err(error) => {
+            return Err(line_error(path, error.to_string()));
+        }
+    }
+}
+
+/// Writes the lines as SVG polylines over a black `res_x` x `res_y` rectangle.
+pub fn save_lines_svg(
+    path: &str,
+    lines: &Vec<Vec<Vec2D>>,
+    res_x: usize,
+    res_y: usize,
+    style: &LineStyle,
+) -> Result<(), Box<dyn Error>> {
+    let [red, green, blue] = style.color;
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\">\n\
+        <rect width=\"{0}\" height=\"{1}\" fill=\"#000000\"/>\n\
+        <g fill=\"none\" stroke=\"#{2:02x}{3:02x}{4:02x}\" stroke-width=\"{5}\" \
+        stroke-linecap=\"round\" stroke-linejoin=\"round\">\n",
+        res_x, res_y, red, green, blue, style.width
+    );
+
+    for line in lines {
+        svg.push_str("<polyline points=\"");
+        for (index, point) in line.iter().enumerate() {
+            if index > 0 {
+                svg.push(' ');
+            }
+            svg.push_str(&format!("{:.2},{:.2}", point.x, point.y));
+        }
+        svg.push_str("\"/>\n");
+    }
+    svg.push_str("</g>\n</svg>\n");
+
+    match fs::write(path, svg) {
+        Ok(_) => {
+            return Ok(());
+        }
+        Err(error) => {
+            return Err(line_error(path, error.to_string()));
+        }
+    }
+}
+
+fn line_error(path: &str, details: String) -> Box<dyn Error> {
+    return classified(
+        ErrorKind::Io,
+        format!(
+            "Line Rendering Error: cannot save \'{}\'! Details: {}",
+            path, details
+        ),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::flow_field::generate_flow_field;
+
+    const RES_X: usize = 96;
+    const RES_Y: usize = 64;
+
+    fn uniform(x: f32, y: f32) -> FlowField {
+        let mut flow_field = FlowField::new(RES_X * RES_Y);
+        flow_field.x.fill(x);
+        flow_field.y.fill(y);
+
+        return flow_field;
+    }
+
+    fn closest_distance(lines: &[Vec<Vec2D>], first: usize, second: usize) -> f32 {
+        return lines[first]
+            .iter()
+            .flat_map(|a| {
+                lines[second]
+                    .iter()
+                    .map(move |b| ((a.x - b.x).powi(2) + (a.y - b.y).powi(2)).sqrt())
+            })
+            .fold(f32::MAX, f32::min);
+    }
+
+    fn directory() -> std::path::PathBuf {
+        let directory = std::env::temp_dir().join(format!(
+            "fluid_simulation_streamlines_{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&directory).unwrap();
+
+        return directory;
+    }
+
+    #[test]
+    fn colors_are_parsed() {
+        assert_eq!(parse_color("#ff8000"), Some([255, 128, 0]));
+        assert_eq!(parse_color("#FFFFFF"), Some([255, 255, 255]));
+        assert_eq!(parse_color("ff8000"), None);
+        assert_eq!(parse_color("#ff80"), None);
+        assert_eq!(parse_color("#gg8000"), None);
+    }
+
+    #[test]
+    fn uniform_flow_gives_parallel_evenly_spaced_lines() {
+        let lines = streamlines(&uniform(0.5, 0.0), RES_X, RES_Y, 8.0);
+
+        assert!(lines.len() >= 6);
+        for line in &lines {
+            let first = &line[0];
+            assert!(line.iter().all(|point| (point.y - first.y).abs() < 1e-3));
+        }
+
+        let mut rows: Vec<f32> = lines.iter().map(|line| line[0].y).collect();
+        rows.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        for (row, next) in rows.iter().zip(rows.iter().skip(1)) {
+            assert!((next - row - 8.0).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn streamlines_of_curl_noise_keep_their_distance() {
+        let mut flow_field = FlowField::new(RES_X * RES_Y);
+        generate_flow_field(
+            &mut flow_field,
+            &mut Vec::new(),
+            RES_X,
+            RES_Y,
+            20.0,
+            0.0,
+            0.0,
+            0.0,
+        );
+
+        let lines = streamlines(&flow_field, RES_X, RES_Y, 6.0);
+
+        assert!(lines.len() > 5);
+        for first in 0..lines.len() {
+            for second in first + 1..lines.len() {
+                assert!(closest_distance(&lines, first, second) >= 3.0 - 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn pathlines_follow_the_flow() {
+        let mut pathlines = Pathlines::new(RES_X, RES_Y, 16.0, 4);
+
+        for _ in 0..10 {
+            pathlines.advance(&uniform(0.0, 1.0));
+        }
+
+        let lines = pathlines.lines();
+        assert!(lines.len() > 1);
+        for line in &lines {
+            assert_eq!(line.len(), 5);
+            assert!(line[0].y < line[4].y || line[4].y == (RES_Y - 1) as f32);
+        }
+    }
+
+    #[test]
+    fn lines_are_saved_as_png_and_svg() {
+        let directory = directory();
+        let lines = vec![vec![Vec2D { x: 10.0, y: 20.0 }, Vec2D { x: 80.0, y: 20.0 }]];
+        let style = LineStyle {
+            width: 3.0,
+            color: [255, 128, 0],
+        };
+        let png = directory.join("lines.png");
+        let svg = directory.join("lines.svg");
+
+        save_lines_png(png.to_str().unwrap(), &lines, RES_X, RES_Y, &style).unwrap();
+        save_lines_svg(svg.to_str().unwrap(), &lines, RES_X, RES_Y, &style).unwrap();
+
+        let image = image::open(&png).unwrap().into_rgb8();
+        assert_eq!(image.dimensions(), (RES_X as u32, RES_Y as u32));
+        assert_eq!(image.get_pixel(40, 20).0, [255, 128, 0]);
+        assert_eq!(image.get_pixel(40, 21).0, [255, 128, 0]);
+        assert_eq!(image.get_pixel(40, 30).0, [0, 0, 0]);
+
+        let svg = fs::read_to_string(&svg).unwrap();
+        assert!(svg.contains("stroke=\"#ff8000\" stroke-width=\"3\""));
+        assert!(svg.contains("<polyline points=\"10.00,20.00 80.00,20.00\"/>"));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,597 @@
+use crate::cli::Overrides;
+use crate::config_file::{
+    configuration_format, explain_unknown_key, strip_comments, ConfigurationFormat,
+};
+use crate::error_kind::{classified, exit_code, ErrorKind};
+use crate::progress::ProgressMode;
+use crate::sim_ctrl::run;
+use crate::utility::Configuration;
+
+use console::style;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::{error::Error, fs, path::Path, sync::Mutex, thread, time::Instant};
+
+/// A batch of runs: the `base` configuration with every combination of the `sweep` values.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SweepConfiguration {
+    pub base: Map<String, Value>,
+    pub sweep: Map<String, Value>,
+    #[serde(default = "default_concurrent_runs")]
+    pub concurrent_runs: usize,
+}
+
+/// One run of a sweep, written to its own subdirectory of the base output directory.
+#[derive(Debug)]
+pub struct Variant {
+    pub name: String,
+    pub parameters: Map<String, Value>,
+    pub config: Configuration,
+}
+
+fn default_concurrent_runs() -> usize {
+    return 1;
+}
+
+pub fn load_sweep_configuration(
+    sweep_file_path: &str,
+) -> Result<SweepConfiguration, Box<dyn Error>> {
+    let format = configuration_format(sweep_file_path);
+
+    let contents = match fs::read_to_string(Path::new(sweep_file_path)) {
+        Ok(contents) => contents,
+        Err(error) => {
+            return Err(classified(
+                ErrorKind::Configuration,
+                format!(
+                    "Sweep File Error: cannot open sweep file \'{}\'! Details: {}",
+                    sweep_file_path, error
+                ),
+            ));
+        }
+    };
+
+    let result = match format {
+        ConfigurationFormat::Json => {
+            serde_json::from_str(&strip_comments(&contents)).map_err(|error| error.to_string())
+        }
+        ConfigurationFormat::Toml => toml::from_str(&contents).map_err(|error| error.to_string()),
+        ConfigurationFormat::Yaml => {
+            serde_yaml_ng::from_str(&contents).map_err(|error| error.to_string())
+        }
+    };
+
+    match result {
+        Ok(sweep) => {
+            return Ok(sweep);
+        }
+        Err(error) => {
+            return Err(classified(
+                ErrorKind::Configuration,
+                format!(
+                    "Sweep File Error: contents of the sweep file cannot be read! Details: {}",
+                    explain_unknown_key(error.trim_end())
+                ),
+            ));
+        }
+    }
+}
+
+/// Values of one swept key: a list, or a `{"from", "to", "step"}` range including both ends.
+pub fn sweep_values(key: &str, values: &Value) -> Result<Vec<Value>, String> {
+    let range = |name: &str| values.get(name).and_then(Value::as_f64);
+
+    match values {
+        Value::Array(values) if values.is_empty() == false => {
+            return Ok(values.clone());
+        }
+        Value::Object(object) if object.len() == 3 => {
+            let (from, to, step) = match (range("from"), range("to"), range("step")) {
+                (Some(from), Some(to), Some(step)) if step > 0.0 && from <= to => (from, to, step),
+                _ => {
+                    return Err(format!(
+                        "Sweep Error: invalid range of \'{}\'! Expected from <= to and step > 0",
+                        key
+                    ));
+                }
+            };
+            let integers = ["from", "to", "step"]
+                .iter()
+                .all(|name| values[name].is_i64() || values[name].is_u64());
+            let count = ((to - from) / step + 1e-9).floor() as usize + 1;
+
+            return Ok((0..count)
+                .map(|index| {
+                    if integers {
+                        json!(
+                            values["from"].as_i64().unwrap_or(0)
+                                + index as i64 * values["step"].as_i64().unwrap_or(0)
+                        )
+                    } else {
+                        // Rounded, so 0.1 steps give 0.3 rather than 0.30000000000000004.
+                        json!(((from + index as f64 * step) * 1e9).round() / 1e9)
+                    }
+                })
+                .collect());
+        }
+        _ => {
+            return Err(format!(
+                "Sweep Error: invalid values of \'{}\'! Expected a non-empty list or a \
+                {{\"from\", \"to\", \"step\"}} range",
+                key
+            ));
+        }
+    }
+}
+
+/// Expands the sweep into the Cartesian product of its values (the last key changes fastest).
+pub fn expand_sweep(
+    sweep: &SweepConfiguration,
+    overrides: &Overrides,
+) -> Result<Vec<Variant>, Box<dyn Error>> {
+    let mut combinations: Vec<Map<String, Value>> = vec![Map::new()];
+
+    for (key, values) in &sweep.sweep {
+        let values = match sweep_values(key, values) {
+            Ok(values) => values,
+            Err(error) => {
+                return Err(classified(ErrorKind::Configuration, error));
+            }
+        };
+
+        combinations = combinations
+            .iter()
+            .flat_map(|combination| {
+                values.iter().map(move |value| {
+                    let mut combination = combination.clone();
+                    combination.insert(key.clone(), value.clone());
+                    combination
+                })
+            })
+            .collect();
+    }
+
+    let output_directory_path = match &overrides.output_directory_path {
+        Some(path) => path.clone(),
+        None => match sweep.base.get("output_directory_path") {
+            Some(Value::String(path)) => path.clone(),
+            _ => String::from("frames"),
+        },
+    };
+    let variant_overrides = Overrides {
+        output_directory_path: None,
+        ..overrides.clone()
+    };
+    let mut variants = Vec::new();
+
+    for (index, parameters) in combinations.into_iter().enumerate() {
+        let name = variant_name(index, &parameters);
+        let mut values = sweep.base.clone();
+
+        values.extend(parameters.clone());
+        values.insert(
+            String::from("output_directory_path"),
+            json!(format!("{}/{}", output_directory_path, name)),
+        );
+
+        let mut config: Configuration = match serde_json::from_value(Value::Object(values)) {
+            Ok(config) => config,
+            Err(error) => {
+                return Err(classified(
+                    ErrorKind::Configuration,
+                    format!(
+                        "Sweep Error: configuration of variant \'{}\' cannot be read! Details: {}",
+                        name,
+                        explain_unknown_key(&error.to_string())
+                    ),
+                ));
+            }
+        };
+        variant_overrides.apply(&mut config);
+
+        // Concurrent runs share the cores unless the configuration says otherwise.
+        if sweep.concurrent_runs > 1 && config.threads.is_none() {
+            config.threads = Some((rayon::current_num_threads() / sweep.concurrent_runs).max(1));
+        }
+
+        variants.push(Variant {
+            name,
+            parameters,
+            config,
+        });
+    }
+
+    return Ok(variants);
+}
+
+/// Runs every variant and writes 'index.json' into the base output directory.
+pub fn run_sweep(sweep: SweepConfiguration, overrides: &Overrides) -> Result<(), Box<dyn Error>> {
+    if sweep.concurrent_runs == 0 {
+        return Err(classified(
+            ErrorKind::Configuration,
+            String::from("Sweep Error: 'concurrent_runs' must be at least 1!"),
+        ));
+    }
+
+    let variants = expand_sweep(&sweep, overrides)?;
+
+    for variant in &variants {
+        if let Err(error) = variant.config.check() {
+            return Err(classified(
+                ErrorKind::Configuration,
+                format!(
+                    "Sweep Error: variant \'{}\' is invalid! {}",
+                    variant.name, error
+                ),
+            ));
+        }
+    }
+
+    let index_directory = match Path::new(&variants[0].config.output_directory_path).parent() {
+        Some(parent) => parent.to_path_buf(),
+        None => unreachable!(),
+    };
+
+    // No headings between the JSON lines of the runs.
+    let headings = variants
+        .iter()
+        .all(|variant| variant.config.progress != ProgressMode::Json);
+
+    if headings {
+        println!(
+            "{}\n",
+            style(format!(
+                "Sweeping {} variant(s) of {}, {} at a time:",
+                variants.len(),
+                sweep
+                    .sweep
+                    .keys()
+                    .map(|key| key.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(", "),
+                sweep.concurrent_runs
+            ))
+            .bold()
+            .underlined()
+            .green()
+        );
+    }
+
+    // Index entries, filled in by the runs as they finish.
+    let entries: Vec<Mutex<Value>> = variants
+        .iter()
+        .map(|variant| Mutex::new(index_entry(variant, Err((String::from("not run"), 1)), 0.0)))
+        .collect();
+    let queue = Mutex::new(variants.iter().zip(entries.iter()));
+
+    thread::scope(|scope| {
+        for _ in 0..sweep.concurrent_runs.min(variants.len()) {
+            let queue = &queue;
+
+            scope.spawn(move || loop {
+                let next = match queue.lock() {
+                    Ok(mut queue) => queue.next(),
+                    Err(_) => None,
+                };
+                let (variant, entry) = match next {
+                    Some(next) => next,
+                    None => {
+                        return;
+                    }
+                };
+
+                if headings {
+                    println!(
+                        "{}",
+                        style(format!("Variant \'{}\':", variant.name))
+                            .bold()
+                            .green()
+                    );
+                }
+
+                let start = Instant::now();
+                let mut config = variant.config.clone();
+                config.run = Some(variant.name.clone());
+
+                let result =
+                    run(config).map_err(|error| (error.to_string(), exit_code(error.as_ref())));
+
+                if let Ok(mut entry) = entry.lock() {
+                    *entry = index_entry(variant, result, start.elapsed().as_secs_f64());
+                }
+            });
+        }
+    });
+
+    let entries: Vec<Value> = entries
+        .into_iter()
+        .map(|entry| entry.into_inner().unwrap_or(Value::Null))
+        .collect();
+    let failures = entries
+        .iter()
+        .filter(|entry| entry["status"] != "complete")
+        .count();
+
+    let index_path = index_directory.join("index.json");
+    let _ = fs::create_dir_all(&index_directory);
+    let index = json!({
+        "base": sweep.base,
+        "sweep": sweep.sweep,
+        "variants": entries,
+    });
+
+    match serde_json::to_string_pretty(&index)
+        .map_err(|error| error.to_string())
+        .and_then(|index| fs::write(&index_path, index).map_err(|error| error.to_string()))
+    {
+        Ok(_) => {}
+        Err(error) => {
+            return Err(classified(
+                ErrorKind::Io,
+                format!(
+                    "Sweep Error: cannot write the index \'{}\'! Details: {}",
+                    index_path.display(),
+                    error
+                ),
+            ));
+        }
+    }
+
+    if failures > 0 {
+        let message = format!(
+            "Sweep Error: {} of {} variant(s) failed! Details: \'{}\'",
+            failures,
+            variants.len(),
+            index_path.display()
+        );
+
+        // The sweep exits like its first failed variant would have.
+        let kind = entries
+            .iter()
+            .find_map(|entry| entry["exit_code"].as_u64())
+            .and_then(|code| ErrorKind::from_exit_code(code as u8));
+
+        match kind {
+            Some(kind) => {
+                return Err(classified(kind, message));
+            }
+            None => {
+                return Err(message.into());
+            }
+        }
+    }
+
+    return Ok(());
+}
+
+/// A completed run records the directory it actually wrote to, which the 'timestamped' output
+/// policy places below the configured one.
+fn index_entry(variant: &Variant, result: Result<String, (String, u8)>, seconds: f64) -> Value {
+    let output_directory_path = match &result {
+        Ok(output_directory_path) => output_directory_path,
+        Err(_) => &variant.config.output_directory_path,
+    };
+    let mut entry = json!({
+        "name": variant.name,
+        "output_directory_path": output_directory_path,
+        "parameters": variant.parameters,
+        "status": "complete",
+        "seconds": seconds,
+    });
+
+    if let Err((error, exit_code)) = result {
+        entry["status"] = json!("failed");
+        entry["error"] = json!(error);
+        entry["exit_code"] = json!(exit_code);
+    }
+
+    return entry;
+}
+
+/// 'NNN_key-value_key-value', with characters unsuitable for file names replaced by '_'.
+fn variant_name(index: usize, parameters: &Map<String, Value>) -> String {
+    let mut name = format!("{:03}", index);
+
+    for (key, value) in parameters {
+        let value = match value {
+            Value::String(value) => value.clone(),
+            value => value.to_string(),
+        };
+        let value: String = value
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+
+        name.push_str(&format!("_{}-{}", key, value));
+    }
+
+    return name;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sweep(sweep: Value) -> SweepConfiguration {
+        return serde_json::from_value(json!({
+            "base": {
+                "mass_distr_file_path": "mass.png",
+                "output_directory_path": "sweeps",
+                "frames_number": 10,
+            },
+            "sweep": sweep,
+        }))
+        .unwrap();
+    }
+
+    #[test]
+    fn ranges_include_both_ends() {
+        assert_eq!(
+            sweep_values(
+                "simulation_factor",
+                &json!({"from": 4, "to": 12, "step": 4})
+            )
+            .unwrap(),
+            vec![json!(4), json!(8), json!(12)]
+        );
+        assert_eq!(
+            sweep_values("viscosity", &json!({"from": 0.0, "to": 0.3, "step": 0.1})).unwrap(),
+            vec![json!(0.0), json!(0.1), json!(0.2), json!(0.3)]
+        );
+        assert_eq!(
+            sweep_values("seed", &json!([1, 5])).unwrap(),
+            vec![json!(1), json!(5)]
+        );
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        assert!(sweep_values("seed", &json!([])).is_err());
+        assert!(sweep_values("seed", &json!(3)).is_err());
+        assert!(sweep_values("viscosity", &json!({"from": 1.0, "to": 0.0, "step": 0.1})).is_err());
+        assert!(sweep_values("viscosity", &json!({"from": 0.0, "to": 1.0, "step": 0})).is_err());
+        assert!(sweep_values("viscosity", &json!({"from": 0.0, "to": 1.0})).is_err());
+    }
+
+    #[test]
+    fn sweep_expands_to_the_cartesian_product() {
+        let sweep = sweep(json!({
+            "flow_field_scale": [100.0, 200.0, 300.0],
+            "velocity_mode": ["curl_noise", "navier_stokes"],
+        }));
+        let overrides = Overrides {
+            frames_number: Some(2),
+            ..Overrides::default()
+        };
+
+        let variants = expand_sweep(&sweep, &overrides).unwrap();
+
+        assert_eq!(variants.len(), 6);
+        assert_eq!(
+            variants[1].name,
+            "001_flow_field_scale-100.0_velocity_mode-navier_stokes"
+        );
+        assert_eq!(
+            variants[1].config.output_directory_path,
+            "sweeps/001_flow_field_scale-100.0_velocity_mode-navier_stokes"
+        );
+        assert_eq!(variants[4].config.flow_field_scale, 300.0);
+        assert!(variants
+            .iter()
+            .all(|variant| variant.config.frames_number == 2));
+    }
+
+    #[test]
+    fn unknown_keys_are_reported() {
+        let error = expand_sweep(
+            &sweep(json!({"flow_field_scal": [100.0]})),
+            &Overrides::default(),
+        )
+        .unwrap_err();
+
+        assert!(error
+            .to_string()
+            .contains("did you mean 'flow_field_scale'?"));
+    }
+
+    #[test]
+    fn every_variant_is_run_and_indexed() {
+        let directory =
+            std::env::temp_dir().join(format!("fluid_simulation_sweep_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let mass_distr_file_path = directory.join("mass.png");
+        image::GrayImage::from_fn(64, 48, |x, _| image::Luma([(x * 4) as u8]))
+            .save(&mass_distr_file_path)
+            .unwrap();
+
+        let sweep: SweepConfiguration = serde_json::from_value(json!({
+            "base": {
+                "mass_distr_file_path": mass_distr_file_path,
+                "output_directory_path": directory.join("runs"),
+                "frames_number": 1,
+                "simulation_factor": 1,
+            },
+            "sweep": {"seed": [1, 2]},
+            "concurrent_runs": 2,
+        }))
+        .unwrap();
+
+        run_sweep(sweep, &Overrides::default()).unwrap();
+
+        let index: Value = serde_json::from_str(
+            &std::fs::read_to_string(directory.join("runs").join("index.json")).unwrap(),
+        )
+        .unwrap();
+        let variants = index["variants"].as_array().unwrap();
+
+        assert_eq!(variants.len(), 2);
+        for (variant, seed) in variants.iter().zip([1, 2]) {
+            assert_eq!(variant["status"], "complete");
+            assert_eq!(variant["parameters"]["seed"], seed);
+            assert!(
+                Path::new(variant["output_directory_path"].as_str().unwrap())
+                    .join("frame_0.png")
+                    .is_file()
+            );
+        }
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn timestamped_variants_are_indexed_by_their_run_directory() {
+        let directory = std::env::temp_dir().join(format!(
+            "fluid_simulation_sweep_timestamped_{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&directory).unwrap();
+        let mass_distr_file_path = directory.join("mass.png");
+        image::GrayImage::from_fn(64, 48, |x, _| image::Luma([(x * 4) as u8]))
+            .save(&mass_distr_file_path)
+            .unwrap();
+
+        let sweep: SweepConfiguration = serde_json::from_value(json!({
+            "base": {
+                "mass_distr_file_path": mass_distr_file_path,
+                "output_directory_path": directory.join("runs"),
+                "output_policy": "timestamped",
+                "frames_number": 1,
+                "simulation_factor": 1,
+            },
+            "sweep": {"seed": [1]},
+        }))
+        .unwrap();
+
+        run_sweep(sweep, &Overrides::default()).unwrap();
+
+        let index: Value = serde_json::from_str(
+            &std::fs::read_to_string(directory.join("runs").join("index.json")).unwrap(),
+        )
+        .unwrap();
+        let output_directory_path = Path::new(
+            index["variants"][0]["output_directory_path"]
+                .as_str()
+                .unwrap(),
+        );
+
+        assert_eq!(
+            output_directory_path.parent().unwrap(),
+            directory.join("runs").join("000_seed-1")
+        );
+        assert!(output_directory_path
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("run_"));
+        assert!(output_directory_path.join("frame_0.png").is_file());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -1,56 +1,682 @@
+use crate::animation::ParameterTrack;
+use crate::frame_output::OutputFormat;
+use crate::navier_stokes::VelocityMode;
+use crate::output_directory::{existing_ancestor, existing_frames, OutputPolicy};
+use crate::particles::{ParticleIntegrator, ParticleLayer, ParticleRendering, ParticleSeeding};
+use crate::progress::ProgressMode;
+use crate::save_frame::{PngColor, PngCompression, PngFilter, PngOptions, ToneCurve};
+use crate::streamlines::{parse_color, LineFormat, VelocityRendering};
+
 use serde::Deserialize;
 
-use std::{error::Error, path::Path};
+use std::{
+    error::Error,
+    fmt,
+    fs::{self, OpenOptions},
+    path::Path,
+};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Configuration {
     pub mass_distr_file_path: String,
+    #[serde(default = "default_output_directory_path")]
     pub output_directory_path: String,
+    #[serde(default = "default_true")]
+    pub create_output_directory: bool,
+    #[serde(default)]
+    pub output_policy: OutputPolicy,
+    #[serde(default = "default_frames_number")]
     pub frames_number: usize,
+    #[serde(default = "default_simulation_factor")]
     pub simulation_factor: usize,
+    #[serde(default = "default_target_resolution")]
     pub target_resolution: usize,
+    #[serde(default = "default_flow_field_scale")]
     pub flow_field_scale: f64,
+    #[serde(default = "default_true")]
     pub dynamize_flow_field: bool,
+    #[serde(default = "default_true")]
     pub randomize_flow_field: bool,
+    #[serde(default)]
+    pub dynamic_mode: DynamicMode,
+    #[serde(default = "default_keyframe_interval")]
+    pub keyframe_interval: usize,
+    #[serde(default = "default_coarse_factor")]
+    pub coarse_factor: usize,
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub velocity_mode: VelocityMode,
+    #[serde(default)]
+    pub viscosity: f32,
+    #[serde(default = "default_pressure_iterations")]
+    pub pressure_iterations: usize,
+    #[serde(default)]
+    pub noise_forcing: f32,
+    #[serde(default)]
+    pub vorticity_confinement: f32,
+    #[serde(default)]
+    pub obstacle_mask_file_path: Option<String>,
+    #[serde(default)]
+    pub buoyancy: f32,
+    #[serde(default)]
+    pub density_weight: f32,
+    #[serde(default = "default_gravity_direction")]
+    pub gravity_direction: [f32; 2],
+    #[serde(default)]
+    pub cooling_rate: f32,
+    #[serde(default)]
+    pub temperature_file_path: Option<String>,
+    #[serde(default)]
+    pub particles_number: usize,
+    #[serde(default)]
+    pub particle_seeding: ParticleSeeding,
+    #[serde(default)]
+    pub particle_integrator: ParticleIntegrator,
+    #[serde(default)]
+    pub particle_rendering: ParticleRendering,
+    #[serde(default)]
+    pub particle_layer: ParticleLayer,
+    #[serde(default = "default_particle_trail_fade")]
+    pub particle_trail_fade: f32,
+    #[serde(default)]
+    pub particle_csv: bool,
+    #[serde(default)]
+    pub decay_rate: f32,
+    #[serde(default)]
+    pub decay_threshold: f32,
+    #[serde(default)]
+    pub decay_map_file_path: Option<String>,
+    #[serde(default)]
+    pub reaction_diffusion: bool,
+    #[serde(default = "default_feed_rate")]
+    pub feed_rate: f32,
+    #[serde(default = "default_kill_rate")]
+    pub kill_rate: f32,
+    #[serde(default = "default_diffusion_u")]
+    pub diffusion_u: f32,
+    #[serde(default = "default_diffusion_v")]
+    pub diffusion_v: f32,
+    #[serde(default = "default_reaction_steps")]
+    pub reaction_steps: usize,
+    #[serde(default)]
+    pub velocity_rendering: VelocityRendering,
+    #[serde(default)]
+    pub line_format: LineFormat,
+    #[serde(default = "default_line_spacing")]
+    pub line_spacing: f32,
+    #[serde(default = "default_line_width")]
+    pub line_width: f32,
+    #[serde(default = "default_line_color")]
+    pub line_color: String,
+    #[serde(default = "default_pathline_length")]
+    pub pathline_length: usize,
+    #[serde(default)]
+    pub threads: Option<usize>,
+    #[serde(default)]
+    pub encoder_threads: Option<usize>,
+    #[serde(default = "default_encoder_workers")]
+    pub encoder_workers: usize,
+    #[serde(default = "default_encoder_queue_size")]
+    pub encoder_queue_size: usize,
+    #[serde(default)]
+    pub progress: ProgressMode,
+    /// Name of the run in the JSON progress lines; set by sweeps for every variant.
+    #[serde(skip)]
+    pub run: Option<String>,
+    #[serde(default)]
+    pub output_format: OutputFormat,
+    #[serde(default)]
+    pub png_compression: PngCompression,
+    #[serde(default)]
+    pub png_filter: PngFilter,
+    #[serde(default = "default_png_bit_depth")]
+    pub png_bit_depth: usize,
+    #[serde(default)]
+    pub png_color: PngColor,
+    #[serde(default = "default_tone_midpoint")]
+    pub tone_midpoint: f32,
+    #[serde(default = "default_tone_slope")]
+    pub tone_slope: f32,
+    #[serde(default)]
+    pub parameter_tracks: Vec<ParameterTrack>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DynamicMode {
+    Full,
+    Keyframes,
+    Coarse,
+}
+
+impl Default for DynamicMode {
+    fn default() -> Self {
+        return DynamicMode::Full;
+    }
+}
+
+impl fmt::Display for DynamicMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DynamicMode::Full => write!(f, "full"),
+            DynamicMode::Keyframes => write!(f, "keyframes"),
+            DynamicMode::Coarse => write!(f, "coarse"),
+        }
+    }
+}
+
+fn default_output_directory_path() -> String {
+    return String::from("frames");
+}
+
+fn default_frames_number() -> usize {
+    return 1000;
+}
+
+fn default_simulation_factor() -> usize {
+    return 8;
+}
+
+fn default_target_resolution() -> usize {
+    return 1080;
+}
+
+fn default_flow_field_scale() -> f64 {
+    return 300.0;
+}
+
+fn default_true() -> bool {
+    return true;
+}
+
+fn default_keyframe_interval() -> usize {
+    return 8;
+}
+
+fn default_coarse_factor() -> usize {
+    return 4;
+}
+
+fn default_pressure_iterations() -> usize {
+    return 40;
+}
+
+fn default_gravity_direction() -> [f32; 2] {
+    return [0.0, 1.0];
+}
+
+fn default_particle_trail_fade() -> f32 {
+    return 0.9;
+}
+
+fn default_feed_rate() -> f32 {
+    return 0.055;
+}
+
+fn default_kill_rate() -> f32 {
+    return 0.062;
+}
+
+fn default_diffusion_u() -> f32 {
+    return 0.2;
+}
+
+fn default_diffusion_v() -> f32 {
+    return 0.1;
+}
+
+fn default_reaction_steps() -> usize {
+    return 1;
+}
+
+fn default_line_spacing() -> f32 {
+    return 10.0;
+}
+
+fn default_line_width() -> f32 {
+    return 1.0;
+}
+
+fn default_line_color() -> String {
+    return String::from("#ffffff");
+}
+
+fn default_pathline_length() -> usize {
+    return 64;
+}
+
+fn default_encoder_workers() -> usize {
+    return 2;
+}
+
+fn default_encoder_queue_size() -> usize {
+    return 4;
+}
+
+fn default_tone_midpoint() -> f32 {
+    return 170.0;
+}
+
+fn default_tone_slope() -> f32 {
+    return 0.03;
+}
+
+fn default_png_bit_depth() -> usize {
+    return 8;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigurationProblem {
+    pub field: &'static str,
+    pub value: String,
+    pub expected: String,
+}
+
+impl fmt::Display for ConfigurationProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "\'{}\' = {}: expected {}",
+            self.field, self.value, self.expected
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct ConfigurationErrors {
+    pub problems: Vec<ConfigurationProblem>,
+}
+
+impl fmt::Display for ConfigurationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Configuration Error: {} invalid value(s)!",
+            self.problems.len()
+        )?;
+        for problem in &self.problems {
+            write!(f, "\n  - {}", problem)?;
+        }
+        return Ok(());
+    }
 }
 
+impl Error for ConfigurationErrors {}
+
 impl Configuration {
     pub fn check(&self) -> Result<(), Box<dyn Error>> {
-        if self.mass_distr_file_path == "" {
-            return Err("Configuration Error: value of the parameter \'mass_distr_file_path\' cannot be an empty literal!".into());
+        let problems = self.problems();
+
+        if problems.is_empty() {
+            return Ok(());
         }
-        if Path::new(&self.mass_distr_file_path).is_file() == false {
-            return Err(format!(
-                "Configuration Error: file \'{}\' does not exist!",
-                self.mass_distr_file_path
-            )
-            .into());
+        return Err(Box::new(ConfigurationErrors { problems }));
+    }
+
+    pub fn problems(&self) -> Vec<ConfigurationProblem> {
+        let mut problems = Vec::new();
+        let mut problem = |field: &'static str, value: String, expected: &str| {
+            problems.push(ConfigurationProblem {
+                field,
+                value,
+                expected: String::from(expected),
+            });
+        };
+
+        if self.mass_distr_file_path.is_empty()
+            || Path::new(&self.mass_distr_file_path).is_file() == false
+        {
+            problem(
+                "mass_distr_file_path",
+                format!("\'{}\'", self.mass_distr_file_path),
+                "a path to an existing graphic file",
+            );
         }
-        if self.output_directory_path == "" {
-            return Err("Configuration Error: value of the parameter \'output_directory_path\' cannot be an empty literal!".into());
+        let output_directory_path = format!("\'{}\'", self.output_directory_path);
+
+        if self.output_directory_path.is_empty() {
+            problem(
+                "output_directory_path",
+                output_directory_path,
+                "a path to a directory",
+            );
+        } else if Path::new(&self.output_directory_path).is_dir() == false {
+            match existing_ancestor(&self.output_directory_path) {
+                _ if self.create_output_directory == false => {
+                    problem(
+                        "output_directory_path",
+                        output_directory_path,
+                        "a path to an existing directory (or \'create_output_directory\' set to true)",
+                    );
+                }
+                Some(ancestor) if ancestor.is_dir() => {
+                    if let Err(error) = check_writable(ancestor) {
+                        problem(
+                            "output_directory_path",
+                            output_directory_path,
+                            &format!(
+                                "a directory that can be created in \'{}\' ({})",
+                                ancestor.display(),
+                                error
+                            ),
+                        );
+                    }
+                }
+                _ => {
+                    problem(
+                        "output_directory_path",
+                        output_directory_path,
+                        "a path to a directory, not to a file",
+                    );
+                }
+            }
+        } else if let Err(error) = check_writable(Path::new(&self.output_directory_path)) {
+            problem(
+                "output_directory_path",
+                output_directory_path,
+                &format!("a writable directory ({})", error),
+            );
+        } else if self.output_policy == OutputPolicy::Refuse {
+            let frames = existing_frames(&self.output_directory_path);
+
+            if frames.is_empty() == false {
+                problem(
+                    "output_directory_path",
+                    output_directory_path,
+                    &format!(
+                        "a directory without frames of an earlier run (found {} file(s) such as \'{}\'; \
+                        set \'output_policy\' to \'overwrite\' or \'timestamped\')",
+                        frames.len(),
+                        frames[0]
+                    ),
+                );
+            }
         }
-        if Path::new(&self.output_directory_path).is_dir() == false {
-            return Err(format!(
-                "Configuration Error: directory \'{}\' does not exist!",
-                self.output_directory_path
-            )
-            .into());
+
+        for (field, value) in [
+            ("frames_number", self.frames_number),
+            ("simulation_factor", self.simulation_factor),
+            ("keyframe_interval", self.keyframe_interval),
+            ("coarse_factor", self.coarse_factor),
+            ("pressure_iterations", self.pressure_iterations),
+            ("pathline_length", self.pathline_length),
+            ("reaction_steps", self.reaction_steps),
+            ("encoder_workers", self.encoder_workers),
+            ("encoder_queue_size", self.encoder_queue_size),
+        ] {
+            if value == 0 {
+                problem(field, value.to_string(), "an integer of at least 1");
+            }
         }
-        if self.simulation_factor == 0 {
-            return Err("Configuration Error: value of the parameter \'simulation_factor\' can not be equal to 0!".into());
+
+        for (field, value) in [
+            ("threads", self.threads),
+            ("encoder_threads", self.encoder_threads),
+        ] {
+            if value == Some(0) {
+                problem(
+                    field,
+                    String::from("0"),
+                    "an integer of at least 1, or null for the default",
+                );
+            }
         }
-        if self.flow_field_scale < 1.0 {
-            return Err("Configuration Error: value of the parameter \'flow_field_scale\' can not be less than 1.0!".into());
+
+        if (self.flow_field_scale >= 1.0 && self.flow_field_scale.is_finite()) == false {
+            problem(
+                "flow_field_scale",
+                format!("{:?}", self.flow_field_scale),
+                "a finite number of at least 1.0",
+            );
+        }
+
+        for (field, value) in [
+            ("viscosity", self.viscosity),
+            ("noise_forcing", self.noise_forcing),
+            ("vorticity_confinement", self.vorticity_confinement),
+            ("buoyancy", self.buoyancy),
+            ("density_weight", self.density_weight),
+            ("decay_rate", self.decay_rate),
+            ("decay_threshold", self.decay_threshold),
+        ] {
+            if (value >= 0.0 && value.is_finite()) == false {
+                problem(
+                    field,
+                    format!("{:?}", value),
+                    "a finite number of at least 0.0",
+                );
+            }
+        }
+
+        if (self.cooling_rate >= 0.0 && self.cooling_rate <= 1.0) == false {
+            problem(
+                "cooling_rate",
+                format!("{:?}", self.cooling_rate),
+                "a number from 0.0 to 1.0",
+            );
+        }
+
+        if (self.particle_trail_fade >= 0.0 && self.particle_trail_fade <= 1.0) == false {
+            problem(
+                "particle_trail_fade",
+                format!("{:?}", self.particle_trail_fade),
+                "a number from 0.0 to 1.0",
+            );
+        }
+
+        for (field, value) in [("feed_rate", self.feed_rate), ("kill_rate", self.kill_rate)] {
+            if (0.0..=1.0).contains(&value) == false {
+                problem(field, format!("{:?}", value), "a number from 0.0 to 1.0");
+            }
+        }
+
+        // The explicit diffusion step is only stable up to a quarter of a cell per step.
+        for (field, value) in [
+            ("diffusion_u", self.diffusion_u),
+            ("diffusion_v", self.diffusion_v),
+        ] {
+            if (0.0..=0.25).contains(&value) == false {
+                problem(field, format!("{:?}", value), "a number from 0.0 to 0.25");
+            }
+        }
+
+        if (self.line_spacing >= 2.0 && self.line_spacing.is_finite()) == false {
+            problem(
+                "line_spacing",
+                format!("{:?}", self.line_spacing),
+                "a finite number of at least 2.0",
+            );
+        }
+
+        if (self.line_width > 0.0 && self.line_width.is_finite()) == false {
+            problem(
+                "line_width",
+                format!("{:?}", self.line_width),
+                "a finite number greater than 0.0",
+            );
+        }
+
+        if parse_color(&self.line_color).is_none() {
+            problem(
+                "line_color",
+                format!("\'{}\'", self.line_color),
+                "a colour in the form \'#rrggbb\'",
+            );
+        }
+
+        let [gravity_x, gravity_y] = self.gravity_direction;
+        if (gravity_x.is_finite() && gravity_y.is_finite()) == false
+            || (gravity_x == 0.0 && gravity_y == 0.0)
+        {
+            problem(
+                "gravity_direction",
+                format!("{:?}", self.gravity_direction),
+                "a non-zero vector [x, y] of finite numbers (y points down the frame)",
+            );
+        }
+
+        if let Some(temperature_file_path) = &self.temperature_file_path {
+            if Path::new(temperature_file_path).is_file() == false {
+                problem(
+                    "temperature_file_path",
+                    format!("\'{}\'", temperature_file_path),
+                    "a path to an existing graphic file, or null to start from the mass distribution",
+                );
+            }
+        }
+
+        if let Some(decay_map_file_path) = &self.decay_map_file_path {
+            if Path::new(decay_map_file_path).is_file() == false {
+                problem(
+                    "decay_map_file_path",
+                    format!("\'{}\'", decay_map_file_path),
+                    "a path to an existing graphic file, or null for a uniform decay",
+                );
+            }
+        }
+
+        if let Some(obstacle_mask_file_path) = &self.obstacle_mask_file_path {
+            if Path::new(obstacle_mask_file_path).is_file() == false {
+                problem(
+                    "obstacle_mask_file_path",
+                    format!("\'{}\'", obstacle_mask_file_path),
+                    "a path to an existing graphic file, or null for no obstacles",
+                );
+            }
+        }
+
+        if self.tone_midpoint.is_finite() == false {
+            problem(
+                "tone_midpoint",
+                format!("{:?}", self.tone_midpoint),
+                "a finite number",
+            );
+        }
+
+        if (self.tone_slope > 0.0 && self.tone_slope.is_finite()) == false {
+            problem(
+                "tone_slope",
+                format!("{:?}", self.tone_slope),
+                "a finite number greater than 0.0",
+            );
+        }
+
+        for (index, track) in self.parameter_tracks.iter().enumerate() {
+            if self.parameter_tracks[..index]
+                .iter()
+                .any(|other| other.parameter == track.parameter)
+            {
+                problem(
+                    "parameter_tracks",
+                    format!("a second '{}' track", track.parameter),
+                    "one track per parameter",
+                );
+            }
+
+            if track.keyframes.is_empty() {
+                problem(
+                    "parameter_tracks",
+                    format!("'{}' without keyframes", track.parameter),
+                    "at least one keyframe per track",
+                );
+            }
+
+            for pair in track.keyframes.windows(2) {
+                if pair[1].frame <= pair[0].frame {
+                    problem(
+                        "parameter_tracks",
+                        format!(
+                            "'{}' keyframe at frame {} after frame {}",
+                            track.parameter, pair[1].frame, pair[0].frame
+                        ),
+                        "keyframes in increasing frame order",
+                    );
+                }
+            }
+
+            for keyframe in &track.keyframes {
+                let (accepted, expected) = track.parameter.accepts(keyframe.value);
+
+                if accepted == false {
+                    problem(
+                        "parameter_tracks",
+                        format!(
+                            "'{}' of {:?} at frame {}",
+                            track.parameter, keyframe.value, keyframe.frame
+                        ),
+                        expected,
+                    );
+                }
+            }
+        }
+
+        match self.png_bit_depth {
+            8 | 16 => {}
+            _ => {
+                problem(
+                    "png_bit_depth",
+                    self.png_bit_depth.to_string(),
+                    "a value from the set {8, 16}",
+                );
+            }
         }
 
         match self.target_resolution {
             480 | 720 | 1080 | 1440 | 2160 => {}
             _ => {
-                return Err("Configuration Error: parameter \'target_resolution\' should take value from the set: {480, 720, 1080, 1440, 2160}!".into());
+                problem(
+                    "target_resolution",
+                    self.target_resolution.to_string(),
+                    "a value from the set {480, 720, 1080, 1440, 2160}",
+                );
             }
         }
 
-        return Ok(());
+        return problems;
+    }
+
+    pub fn png_options(&self) -> PngOptions {
+        return PngOptions {
+            compression: self.png_compression,
+            filter: self.png_filter,
+            bit_depth: self.png_bit_depth,
+            color: self.png_color,
+        };
+    }
+
+    pub fn tone_curve(&self) -> ToneCurve {
+        return ToneCurve {
+            midpoint: self.tone_midpoint,
+            slope: self.tone_slope,
+        };
+    }
+}
+
+fn check_writable(directory: &Path) -> Result<(), std::io::Error> {
+    let probe = directory.join(format!(
+        ".fluid_simulation_write_test_{}",
+        std::process::id()
+    ));
+
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&probe)?;
+    return fs::remove_file(&probe);
+}
+
+pub const SUPPORTED_RESOLUTIONS: [usize; 5] = [480, 720, 1080, 1440, 2160];
+
+pub fn grid_dimensions(target_resolution: usize) -> Option<(usize, usize)> {
+    match target_resolution {
+        480 => Some((640, 460)),
+        720 => Some((1280, 720)),
+        1080 => Some((1920, 1080)),
+        1440 => Some((2560, 1440)),
+        2160 => Some((3840, 2160)),
+        _ => None,
     }
 }
 
@@ -65,3 +691,544 @@ impl Vec2D {
         return (self.x * self.x + self.y * self.y).sqrt();
     }
 }
+
+#[derive(Clone)]
+pub struct FlowField {
+    pub x: Vec<f32>,
+    pub y: Vec<f32>,
+}
+
+impl FlowField {
+    pub fn new(size: usize) -> FlowField {
+        return FlowField {
+            x: vec![0.0; size],
+            y: vec![0.0; size],
+        };
+    }
+
+    pub fn get(&self, index: usize) -> Vec2D {
+        return Vec2D {
+            x: self.x[index],
+            y: self.y[index],
+        };
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Vec2D> + '_ {
+        return self
+            .x
+            .iter()
+            .zip(self.y.iter())
+            .map(|(x, y)| Vec2D { x: *x, y: *y });
+    }
+
+    /// Bilinearly interpolated vector at (x, y), clamped to the grid.
+    pub fn sample(&self, res_x: usize, res_y: usize, x: f32, y: f32) -> Vec2D {
+        let x = x.clamp(0.0, (res_x - 1) as f32);
+        let y = y.clamp(0.0, (res_y - 1) as f32);
+
+        return Vec2D {
+            x: bilinear_sample(&self.x, res_x, res_y, x, y),
+            y: bilinear_sample(&self.y, res_x, res_y, x, y),
+        };
+    }
+}
+
+/// Bilinear interpolation of a grid at (x, y), 0 <= x <= res_x - 1 and 0 <= y <= res_y - 1.
+pub fn bilinear_sample(values: &[f32], res_x: usize, res_y: usize, x: f32, y: f32) -> f32 {
+    let (x0, y0) = (
+        (x.floor() as usize).min(res_x - 2),
+        (y.floor() as usize).min(res_y - 2),
+    );
+    let (t_x, t_y) = (x - x0 as f32, y - y0 as f32);
+    let index = y0 * res_x + x0;
+
+    return (values[index] * (1.0 - t_x) + values[index + 1] * t_x) * (1.0 - t_y)
+        + (values[index + res_x] * (1.0 - t_x) + values[index + res_x + 1] * t_x) * t_y;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animation::{AnimatedParameter, Interpolation, Keyframe};
+
+    fn valid_configuration() -> Configuration {
+        return Configuration {
+            mass_distr_file_path: String::from("mass_distr/mass.bmp"),
+            output_directory_path: std::env::temp_dir().to_string_lossy().into_owned(),
+            create_output_directory: true,
+            output_policy: OutputPolicy::Overwrite,
+            frames_number: 10,
+            simulation_factor: 8,
+            target_resolution: 1080,
+            flow_field_scale: 300.0,
+            dynamize_flow_field: false,
+            randomize_flow_field: false,
+            dynamic_mode: DynamicMode::Full,
+            keyframe_interval: 8,
+            coarse_factor: 4,
+            seed: None,
+            velocity_mode: VelocityMode::CurlNoise,
+            viscosity: 0.0,
+            pressure_iterations: 40,
+            noise_forcing: 0.0,
+            vorticity_confinement: 0.0,
+            obstacle_mask_file_path: None,
+            buoyancy: 0.0,
+            density_weight: 0.0,
+            gravity_direction: [0.0, 1.0],
+            cooling_rate: 0.0,
+            temperature_file_path: None,
+            particles_number: 0,
+            particle_seeding: ParticleSeeding::Random,
+            particle_integrator: ParticleIntegrator::Rk2,
+            particle_rendering: ParticleRendering::Dots,
+            particle_layer: ParticleLayer::Over,
+            particle_trail_fade: 0.9,
+            particle_csv: false,
+            decay_rate: 0.0,
+            decay_threshold: 0.0,
+            decay_map_file_path: None,
+            reaction_diffusion: false,
+            feed_rate: 0.055,
+            kill_rate: 0.062,
+            diffusion_u: 0.2,
+            diffusion_v: 0.1,
+            reaction_steps: 1,
+            velocity_rendering: VelocityRendering::None,
+            line_format: LineFormat::Png,
+            line_spacing: 10.0,
+            line_width: 1.0,
+            line_color: String::from("#ffffff"),
+            pathline_length: 64,
+            threads: None,
+            encoder_threads: None,
+            encoder_workers: 2,
+            encoder_queue_size: 4,
+            progress: ProgressMode::Bar,
+            run: None,
+            output_format: OutputFormat::Png,
+            png_compression: PngCompression::Default,
+            png_filter: PngFilter::Adaptive,
+            png_bit_depth: 8,
+            png_color: PngColor::Gray,
+            tone_midpoint: 170.0,
+            tone_slope: 0.03,
+            parameter_tracks: Vec::new(),
+        };
+    }
+
+    fn check_error(config: Configuration) -> String {
+        return config.check().unwrap_err().to_string();
+    }
+
+    #[test]
+    fn valid_configuration_passes_check() {
+        assert!(valid_configuration().check().is_ok());
+    }
+
+    #[test]
+    fn empty_mass_distr_file_path_is_rejected() {
+        let mut config = valid_configuration();
+        config.mass_distr_file_path = String::new();
+
+        assert!(check_error(config)
+            .contains("'mass_distr_file_path' = '': expected a path to an existing graphic file"));
+    }
+
+    #[test]
+    fn missing_mass_distr_file_is_rejected() {
+        let mut config = valid_configuration();
+        config.mass_distr_file_path = String::from("mass_distr/missing.bmp");
+
+        assert!(check_error(config).contains(
+            "'mass_distr_file_path' = 'mass_distr/missing.bmp': expected a path to an existing graphic file"
+        ));
+    }
+
+    #[test]
+    fn empty_output_directory_path_is_rejected() {
+        let mut config = valid_configuration();
+        config.output_directory_path = String::new();
+
+        assert!(check_error(config)
+            .contains("'output_directory_path' = '': expected a path to a directory"));
+    }
+
+    #[test]
+    fn missing_output_directory_is_rejected() {
+        let mut config = valid_configuration();
+        config.output_directory_path = String::from("missing_directory");
+        config.create_output_directory = false;
+
+        assert!(check_error(config).contains(
+            "'output_directory_path' = 'missing_directory': expected a path to an existing directory"
+        ));
+    }
+
+    #[test]
+    fn missing_output_directory_can_be_created() {
+        let mut config = valid_configuration();
+        config.output_directory_path = String::from("missing_directory/frames");
+
+        assert!(config.check().is_ok());
+
+        config.output_directory_path = String::from("mass_distr/mass.bmp/frames");
+
+        assert!(check_error(config).contains("expected a path to a directory, not to a file"));
+    }
+
+    #[test]
+    fn earlier_frames_are_protected() {
+        let directory =
+            std::env::temp_dir().join(format!("fluid_simulation_refuse_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("frame_0.png"), b"").unwrap();
+
+        let mut config = valid_configuration();
+        config.output_directory_path = directory.to_string_lossy().into_owned();
+        config.output_policy = OutputPolicy::Refuse;
+
+        assert!(check_error(config).contains("found 1 file(s) such as 'frame_0.png'"));
+
+        for policy in [OutputPolicy::Overwrite, OutputPolicy::Timestamped] {
+            let mut config = valid_configuration();
+            config.output_directory_path = directory.to_string_lossy().into_owned();
+            config.output_policy = policy;
+
+            assert!(config.check().is_ok());
+        }
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn zero_simulation_factor_is_rejected() {
+        let mut config = valid_configuration();
+        config.simulation_factor = 0;
+
+        assert!(check_error(config)
+            .contains("'simulation_factor' = 0: expected an integer of at least 1"));
+    }
+
+    #[test]
+    fn invalid_navier_stokes_parameters_are_rejected() {
+        let mut config = valid_configuration();
+        config.velocity_mode = VelocityMode::NavierStokes;
+        config.viscosity = -0.1;
+        config.noise_forcing = f32::NAN;
+        config.vorticity_confinement = f32::INFINITY;
+        config.pressure_iterations = 0;
+        config.obstacle_mask_file_path = Some(String::from("mass_distr/missing.bmp"));
+
+        let error = check_error(config);
+
+        assert!(error.contains("'viscosity' = -0.1: expected a finite number of at least 0.0"));
+        assert!(error.contains("'noise_forcing' = NaN: expected a finite number of at least 0.0"));
+        assert!(error.contains("'vorticity_confinement' = inf: expected a finite number"));
+        assert!(error.contains("'pressure_iterations' = 0: expected an integer of at least 1"));
+        assert!(error.contains("'obstacle_mask_file_path' = 'mass_distr/missing.bmp'"));
+    }
+
+    #[test]
+    fn invalid_reaction_diffusion_parameters_are_rejected() {
+        let mut config = valid_configuration();
+        config.feed_rate = -0.01;
+        config.diffusion_v = 0.5;
+        config.reaction_steps = 0;
+
+        let error = check_error(config);
+
+        assert!(error.contains("'feed_rate' = -0.01: expected a number from 0.0 to 1.0"));
+        assert!(error.contains("'diffusion_v' = 0.5: expected a number from 0.0 to 0.25"));
+        assert!(error.contains("'reaction_steps' = 0: expected an integer of at least 1"));
+    }
+
+    #[test]
+    fn invalid_parameter_tracks_are_rejected() {
+        let keyframe = |frame: usize, value: f64| Keyframe {
+            frame,
+            value,
+            interpolation: Interpolation::Linear,
+        };
+        let mut config = valid_configuration();
+        config.tone_slope = 0.0;
+        config.parameter_tracks = vec![
+            ParameterTrack {
+                parameter: AnimatedParameter::FlowSpeed,
+                keyframes: vec![keyframe(0, 1.0), keyframe(50, -0.5)],
+            },
+            ParameterTrack {
+                parameter: AnimatedParameter::FlowFieldScale,
+                keyframes: vec![keyframe(20, 300.0), keyframe(10, 100.0)],
+            },
+            ParameterTrack {
+                parameter: AnimatedParameter::FlowSpeed,
+                keyframes: Vec::new(),
+            },
+        ];
+
+        let error = check_error(config);
+
+        assert!(error.contains("'tone_slope' = 0.0: expected a finite number greater than 0.0"));
+        assert!(error.contains(
+            "'parameter_tracks' = 'flow_speed' of -0.5 at frame 50: \
+            expected a number from 0.0 to 16.0"
+        ));
+        assert!(error.contains(
+            "'parameter_tracks' = 'flow_field_scale' keyframe at frame 10 after frame 20: \
+            expected keyframes in increasing frame order"
+        ));
+        assert!(error.contains(
+            "'parameter_tracks' = a second 'flow_speed' track: expected one track per parameter"
+        ));
+        assert!(error.contains("'parameter_tracks' = 'flow_speed' without keyframes"));
+    }
+
+    #[test]
+    fn invalid_decay_parameters_are_rejected() {
+        let mut config = valid_configuration();
+        config.decay_rate = f32::NAN;
+        config.decay_threshold = -0.5;
+        config.decay_map_file_path = Some(String::from("mass_distr/missing.bmp"));
+
+        let error = check_error(config);
+
+        assert!(error.contains("'decay_rate' = NaN: expected a finite number of at least 0.0"));
+        assert!(
+            error.contains("'decay_threshold' = -0.5: expected a finite number of at least 0.0")
+        );
+        assert!(error.contains("'decay_map_file_path' = 'mass_distr/missing.bmp'"));
+    }
+
+    #[test]
+    fn invalid_line_parameters_are_rejected() {
+        let mut config = valid_configuration();
+        config.line_spacing = 1.0;
+        config.line_width = 0.0;
+        config.line_color = String::from("white");
+        config.pathline_length = 0;
+
+        let error = check_error(config);
+
+        assert!(error.contains("'line_spacing' = 1.0: expected a finite number of at least 2.0"));
+        assert!(error.contains("'line_width' = 0.0: expected a finite number greater than 0.0"));
+        assert!(error.contains("'line_color' = 'white': expected a colour in the form '#rrggbb'"));
+        assert!(error.contains("'pathline_length' = 0: expected an integer of at least 1"));
+    }
+
+    #[test]
+    fn invalid_buoyancy_parameters_are_rejected() {
+        let mut config = valid_configuration();
+        config.buoyancy = -1.0;
+        config.cooling_rate = 1.5;
+        config.gravity_direction = [0.0, 0.0];
+        config.temperature_file_path = Some(String::from("mass_distr/missing.bmp"));
+
+        let error = check_error(config);
+
+        assert!(error.contains("'buoyancy' = -1.0: expected a finite number of at least 0.0"));
+        assert!(error.contains("'cooling_rate' = 1.5: expected a number from 0.0 to 1.0"));
+        assert!(error.contains("'gravity_direction' = [0.0, 0.0]: expected a non-zero vector"));
+        assert!(error.contains("'temperature_file_path' = 'mass_distr/missing.bmp'"));
+    }
+
+    #[test]
+    fn invalid_particle_parameters_are_rejected() {
+        let mut config = valid_configuration();
+        config.particle_trail_fade = -0.5;
+
+        assert!(check_error(config)
+            .contains("'particle_trail_fade' = -0.5: expected a number from 0.0 to 1.0"));
+    }
+
+    #[test]
+    fn small_flow_field_scale_is_rejected() {
+        let mut config = valid_configuration();
+        config.flow_field_scale = 0.5;
+
+        assert!(check_error(config)
+            .contains("'flow_field_scale' = 0.5: expected a finite number of at least 1.0"));
+    }
+
+    #[test]
+    fn zero_keyframe_interval_is_rejected() {
+        let mut config = valid_configuration();
+        config.keyframe_interval = 0;
+
+        assert!(check_error(config)
+            .contains("'keyframe_interval' = 0: expected an integer of at least 1"));
+    }
+
+    #[test]
+    fn zero_coarse_factor_is_rejected() {
+        let mut config = valid_configuration();
+        config.coarse_factor = 0;
+
+        assert!(
+            check_error(config).contains("'coarse_factor' = 0: expected an integer of at least 1")
+        );
+    }
+
+    #[test]
+    fn zero_threads_are_rejected() {
+        let mut config = valid_configuration();
+        config.threads = Some(0);
+
+        assert!(check_error(config).contains("'threads' = 0: expected an integer of at least 1"));
+
+        let mut config = valid_configuration();
+        config.encoder_threads = Some(0);
+
+        assert!(check_error(config)
+            .contains("'encoder_threads' = 0: expected an integer of at least 1"));
+    }
+
+    #[test]
+    fn empty_encoder_pipeline_is_rejected() {
+        let mut config = valid_configuration();
+        config.encoder_workers = 0;
+
+        assert!(check_error(config)
+            .contains("'encoder_workers' = 0: expected an integer of at least 1"));
+
+        let mut config = valid_configuration();
+        config.encoder_queue_size = 0;
+
+        assert!(check_error(config)
+            .contains("'encoder_queue_size' = 0: expected an integer of at least 1"));
+    }
+
+    #[test]
+    fn unsupported_png_bit_depth_is_rejected() {
+        let mut config = valid_configuration();
+        config.png_bit_depth = 12;
+
+        assert!(check_error(config)
+            .contains("'png_bit_depth' = 12: expected a value from the set {8, 16}"));
+    }
+
+    #[test]
+    fn unsupported_target_resolution_is_rejected() {
+        let mut config = valid_configuration();
+        config.target_resolution = 1000;
+
+        assert!(check_error(config).contains(
+            "'target_resolution' = 1000: expected a value from the set {480, 720, 1080, 1440, 2160}"
+        ));
+    }
+
+    #[test]
+    fn zero_frames_number_is_rejected() {
+        let mut config = valid_configuration();
+        config.frames_number = 0;
+
+        assert!(
+            check_error(config).contains("'frames_number' = 0: expected an integer of at least 1")
+        );
+    }
+
+    #[test]
+    fn non_finite_flow_field_scale_is_rejected() {
+        for scale in [f64::NAN, f64::INFINITY] {
+            let mut config = valid_configuration();
+            config.flow_field_scale = scale;
+
+            assert!(check_error(config).contains(&format!(
+                "'flow_field_scale' = {:?}: expected a finite number of at least 1.0",
+                scale
+            )));
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn unwritable_output_directory_is_rejected() {
+        let mut config = valid_configuration();
+        config.output_directory_path = String::from("/proc");
+
+        assert!(check_error(config)
+            .contains("'output_directory_path' = '/proc': expected a writable directory"));
+    }
+
+    #[test]
+    fn every_problem_is_reported_at_once() {
+        let mut config = valid_configuration();
+        config.mass_distr_file_path = String::new();
+        config.frames_number = 0;
+        config.flow_field_scale = f64::NAN;
+        config.png_bit_depth = 12;
+
+        let problems = config.problems();
+        assert_eq!(
+            problems
+                .iter()
+                .map(|problem| problem.field)
+                .collect::<Vec<&str>>(),
+            vec![
+                "mass_distr_file_path",
+                "frames_number",
+                "flow_field_scale",
+                "png_bit_depth"
+            ]
+        );
+        assert!(check_error(config).starts_with("Configuration Error: 4 invalid value(s)!\n  - "));
+    }
+
+    #[test]
+    fn every_supported_resolution_has_grid_dimensions() {
+        for resolution in SUPPORTED_RESOLUTIONS {
+            assert!(grid_dimensions(resolution).is_some());
+        }
+
+        assert_eq!(grid_dimensions(1080), Some((1920, 1080)));
+        assert_eq!(grid_dimensions(1000), None);
+    }
+
+    #[test]
+    fn optional_keys_take_default_values() {
+        let config: Configuration = serde_json::from_str(
+            r#"{
+                "mass_distr_file_path": "mass_distr/mass.bmp",
+                "output_directory_path": "frames",
+                "frames_number": 1000,
+                "simulation_factor": 8,
+                "target_resolution": 1080,
+                "flow_field_scale": 300.0,
+                "dynamize_flow_field": true,
+                "randomize_flow_field": true
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.dynamic_mode, DynamicMode::Full);
+        assert_eq!(config.keyframe_interval, 8);
+        assert_eq!(config.coarse_factor, 4);
+        assert_eq!(config.seed, None);
+        assert_eq!(config.threads, None);
+        assert_eq!(config.encoder_threads, None);
+        assert_eq!(config.encoder_workers, 2);
+        assert_eq!(config.encoder_queue_size, 4);
+        assert_eq!(config.png_options(), PngOptions::default());
+    }
+
+    #[test]
+    fn flow_field_components_are_stored_separately() {
+        let mut flow_field = FlowField::new(3);
+        flow_field.x[1] = 2.0;
+        flow_field.y[2] = -1.0;
+
+        assert_eq!(flow_field.get(1).x, 2.0);
+        assert_eq!(flow_field.get(1).y, 0.0);
+        assert_eq!(
+            flow_field.iter().map(|value| value.y).collect::<Vec<f32>>(),
+            vec![0.0, 0.0, -1.0]
+        );
+    }
+
+    #[test]
+    fn vec2d_length() {
+        assert_eq!(Vec2D { x: 3.0, y: 4.0 }.length(), 5.0);
+        assert_eq!(Vec2D { x: 0.0, y: 0.0 }.length(), 0.0);
+        assert_eq!(Vec2D { x: -1.0, y: 0.0 }.length(), 1.0);
+    }
+}
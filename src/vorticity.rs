@@ -0,0 +1,163 @@
+use crate::utility::FlowField;
+
+use rayon::prelude::*;
+
+/// Vorticity confinement (Fedkiw, Stam and Jensen, 2001): a force that pushes the velocity
+/// around the local maxima of |curl|, giving back the small vortices smoothed out by the
+/// numerical dissipation of the advection.
+pub struct VorticityConfinement {
+    res_x: usize,
+    res_y: usize,
+    strength: f32,
+    vorticity: Vec<f32>,
+}
+
+impl VorticityConfinement {
+    pub fn new(res_x: usize, res_y: usize, strength: f32) -> VorticityConfinement {
+        return VorticityConfinement {
+            res_x,
+            res_y,
+            strength,
+            vorticity: vec![0.0; res_x * res_y],
+        };
+    }
+
+    /// Curl of the field passed to the last `apply`, zero on the border.
+    pub fn vorticity(&self) -> &Vec<f32> {
+        return &self.vorticity;
+    }
+
+    /// Adds `strength * (N x curl)` to `flow_field`, where N is the normalised gradient of |curl|,
+    /// and limits the speed to 1 again.
+    pub fn apply(&mut self, flow_field: &mut FlowField) {
+        let (res_x, res_y, strength) = (self.res_x, self.res_y, self.strength);
+        let (u, v) = (&flow_field.x, &flow_field.y);
+
+        self.vorticity
+            .par_chunks_mut(res_x)
+            .enumerate()
+            .for_each(|(y, row)| {
+                for (x, value) in row.iter_mut().enumerate() {
+                    let index = y * res_x + x;
+
+                    *value = if x == 0 || y == 0 || x == res_x - 1 || y == res_y - 1 {
+                        0.0
+                    } else {
+                        0.5 * ((v[index + 1] - v[index - 1])
+                            - (u[index + res_x] - u[index - res_x]))
+                    };
+                }
+            });
+
+        let vorticity = &self.vorticity;
+
+        flow_field
+            .x
+            .par_chunks_mut(res_x)
+            .zip(flow_field.y.par_chunks_mut(res_x))
+            .enumerate()
+            .skip(2)
+            .take(res_y.saturating_sub(4))
+            .for_each(|(y, (row_x, row_y))| {
+                for x in 2..res_x - 2 {
+                    let index = y * res_x + x;
+                    let gradient_x =
+                        0.5 * (vorticity[index + 1].abs() - vorticity[index - 1].abs());
+                    let gradient_y =
+                        0.5 * (vorticity[index + res_x].abs() - vorticity[index - res_x].abs());
+                    let length = (gradient_x * gradient_x + gradient_y * gradient_y).sqrt() + 1e-5;
+
+                    row_x[x] += strength * gradient_y / length * vorticity[index];
+                    row_y[x] -= strength * gradient_x / length * vorticity[index];
+                }
+            });
+
+        limit_speed(flow_field);
+    }
+}
+
+/// Scales every vector longer than 1 back to unit length.
+pub fn limit_speed(flow_field: &mut FlowField) {
+    flow_field
+        .x
+        .par_iter_mut()
+        .zip(flow_field.y.par_iter_mut())
+        .for_each(|(u, v)| {
+            let speed = (*u * *u + *v * *v).sqrt();
+
+            if speed > 1.0 {
+                *u /= speed;
+                *v /= speed;
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RES_X: usize = 64;
+    const RES_Y: usize = 48;
+
+    /// A Gaussian vortex turning counter-clockwise around the centre of the grid.
+    fn vortex() -> FlowField {
+        let mut flow_field = FlowField::new(RES_X * RES_Y);
+
+        for index in 0..RES_X * RES_Y {
+            let (x, y) = ((index % RES_X) as f32 - 32.0, (index / RES_X) as f32 - 24.0);
+            let swirl = 0.1 * (-(x * x + y * y) / 50.0).exp();
+
+            flow_field.x[index] = -y * swirl;
+            flow_field.y[index] = x * swirl;
+        }
+
+        return flow_field;
+    }
+
+    fn total_vorticity(confinement: &VorticityConfinement) -> f32 {
+        return confinement
+            .vorticity()
+            .iter()
+            .map(|value| value.abs())
+            .sum();
+    }
+
+    #[test]
+    fn irrotational_field_is_unchanged() {
+        let mut flow_field = FlowField::new(RES_X * RES_Y);
+        flow_field.x.fill(0.5);
+        flow_field.y.fill(-0.25);
+        let mut confinement = VorticityConfinement::new(RES_X, RES_Y, 0.5);
+
+        confinement.apply(&mut flow_field);
+
+        assert!(flow_field
+            .iter()
+            .all(|value| value.x == 0.5 && value.y == -0.25));
+        assert_eq!(total_vorticity(&confinement), 0.0);
+    }
+
+    #[test]
+    fn confinement_spins_vortices_up() {
+        let mut flow_field = vortex();
+        let mut confinement = VorticityConfinement::new(RES_X, RES_Y, 0.2);
+
+        confinement.apply(&mut flow_field);
+        let initial_vorticity = total_vorticity(&confinement);
+        confinement.apply(&mut flow_field);
+
+        assert!(total_vorticity(&confinement) > initial_vorticity);
+        // The vortex keeps its direction of rotation.
+        assert!(flow_field.y[24 * RES_X + 36] > 0.0 && flow_field.x[28 * RES_X + 32] < 0.0);
+    }
+
+    #[test]
+    fn speed_stays_limited() {
+        let mut flow_field = vortex();
+        let mut confinement = VorticityConfinement::new(RES_X, RES_Y, 50.0);
+
+        confinement.apply(&mut flow_field);
+
+        assert!(flow_field.iter().all(|value| value.length() <= 1.0 + 1e-6));
+    }
+}
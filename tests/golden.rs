@@ -0,0 +1,127 @@
+#![allow(clippy::needless_return)]
+
+use fluid_simulation::sim_ctrl::{load_configuration, run};
+
+use image::{GrayImage, Luma};
+use std::path::{Path, PathBuf};
+
+const FRAMES_NUMBER: usize = 2;
+
+fn case_directory(case: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!(
+        "fluid_simulation_golden_{}_{}",
+        std::process::id(),
+        case
+    ));
+    std::fs::create_dir_all(&directory).unwrap();
+
+    return directory;
+}
+
+fn write_mass_distr(directory: &Path) -> PathBuf {
+    let path = directory.join("mass.png");
+    let image = GrayImage::from_fn(320, 230, |x, y| {
+        let dx = x as f32 - 160.0;
+        let dy = y as f32 - 115.0;
+        let ring = ((dx * dx + dy * dy).sqrt() - 70.0).abs() < 20.0;
+        let stripe = (x / 16) % 2 == 0 && y > 180;
+
+        Luma([if ring || stripe { 255 } else { 0 }])
+    });
+    image.save(&path).unwrap();
+
+    return path;
+}
+
+fn run_case(case: &str, options: &str) {
+    let directory = case_directory(case);
+    let mass_distr_file_path = write_mass_distr(&directory);
+    let config_file_path = directory.join("config.json");
+    let golden_directory = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(case);
+
+    std::fs::write(
+        &config_file_path,
+        format!(
+            r#"{{
+                "mass_distr_file_path": {:?},
+                "output_directory_path": {:?},
+                "frames_number": {},
+                "simulation_factor": 4,
+                "target_resolution": 480,
+                "flow_field_scale": 150.0,
+                {}
+            }}"#,
+            mass_distr_file_path.to_str().unwrap(),
+            directory.to_str().unwrap(),
+            FRAMES_NUMBER,
+            options
+        ),
+    )
+    .unwrap();
+
+    run(load_configuration(config_file_path.to_str().unwrap()).unwrap()).unwrap();
+
+    for frame in 0..FRAMES_NUMBER {
+        let file_name = format!("frame_{}.png", frame);
+        let output = image::open(directory.join(&file_name))
+            .unwrap()
+            .into_luma8();
+
+        if std::env::var_os("FLUID_SIMULATION_BLESS").is_some() {
+            std::fs::create_dir_all(&golden_directory).unwrap();
+            output.save(golden_directory.join(&file_name)).unwrap();
+            continue;
+        }
+
+        let golden = image::open(golden_directory.join(&file_name))
+            .unwrap()
+            .into_luma8();
+
+        assert_eq!(output.dimensions(), golden.dimensions());
+        for (x, y, pixel) in output.enumerate_pixels() {
+            let difference = (pixel[0] as i32 - golden.get_pixel(x, y)[0] as i32).abs();
+
+            assert!(
+                difference <= 1,
+                "{}/{}: pixel ({}, {}) differs from the golden image by {}",
+                case,
+                file_name,
+                x,
+                y,
+                difference
+            );
+        }
+    }
+
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn static_flow_field_with_fixed_seed() {
+    run_case(
+        "static_seeded",
+        r#""dynamize_flow_field": false, "randomize_flow_field": true, "seed": 7"#,
+    );
+}
+
+#[test]
+fn dynamic_flow_field_with_keyframes() {
+    run_case(
+        "dynamic_keyframes",
+        r#""dynamize_flow_field": true, "randomize_flow_field": false,
+           "dynamic_mode": "keyframes", "keyframe_interval": 4"#,
+    );
+}
+
+#[test]
+fn navier_stokes_velocity() {
+    run_case(
+        "navier_stokes",
+        r#""dynamize_flow_field": false, "randomize_flow_field": false,
+           "velocity_mode": "navier_stokes", "viscosity": 0.05, "noise_forcing": 0.1,
+           "pressure_iterations": 10"#,
+    );
+}